@@ -0,0 +1,259 @@
+
+use core::fmt;
+use std::rc::Rc;
+
+use crate::lexer::Span;
+
+
+//the syntax tree produced by the parser.
+//every node carries the span of source it was parsed from.
+
+
+#[derive(Debug, Clone)]
+pub struct StatSeq { pub stmts: Vec<Stmt>, pub span: Span } //program is just sequence of statements
+
+#[derive(Debug, Clone)]
+pub struct Stmt { pub kind: StmtKind, pub span: Span }
+
+#[derive(Debug, Clone)]
+pub struct Expr { pub kind: ExprKind, pub span: Span }
+
+
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    Import   { mod_name: String },
+    Assign   { var_name: String, op: AssignOp, expr: Expr },
+    Return   { expr: Option<Expr>, tail_call: bool }, //`tail_call` is set for `return f(...)` inside of `f`
+    Fun      (Rc<FunctionDeclare>),
+    Expr     (Expr),
+    If       (IfStat),
+    While    { condition: Expr, body: StatSeq },
+    For      { elem_name: String, elem_span: Span, array: Expr, body: StatSeq }, //`elem_span` is the one of the element's name
+    Try      { body: StatSeq, catch: Option<Catch>, finally: Option<StatSeq> }, //at least one of `catch` and `finally`
+    Error, //stands in for a statement with syntax errors
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Binary    { op: String, left: Box<Expr>, right: Box<Expr> },
+    Unary     { op: String, operand: Box<Expr> },
+    Int       { value: u64, text: String }, //`text` is the literal as written, f.e. `0xFF`
+    Float     { value: f64, text: String },
+    Str       (String),
+    Format    (Vec<Expr>), //interpolated string, e.g. "sum: {a + b}"
+    Variable  (String),
+    Call      (FunctionCall),
+    ModAccess { mod_name: String, member: FunctionCall },
+    Array     (Vec<Expr>),
+    Propagate (Box<Expr>), //`value?`, the function it is in returns `value` right away if it is an error
+    Error, //stands in for an expression with syntax errors
+}
+
+
+#[derive(Debug, Clone)] pub struct FunctionDeclare  { pub name: String, pub args: Vec<Param>, pub rest: Option<Param>, pub body: StatSeq }
+#[derive(Debug, Clone)] pub struct Param            { pub name: String, pub default: Option<Expr>, pub span: Span } //`b := 2` in `fun f(a, b := 2)`
+#[derive(Debug, Clone)] pub struct FunctionCall     { pub name: String, pub args: Vec<Expr> }
+#[derive(Debug, Clone)] pub struct Catch            { pub name: String, pub span: Span, pub body: StatSeq } //`catch (e) { ... }`, `span` is the one of `e`
+#[derive(Debug, Clone)] pub struct IfStat           { pub condition: Expr, pub if_block: StatSeq, pub else_block: Option<ElseBlock> }
+
+#[derive(Debug, Clone)]
+pub enum ElseBlock {
+    Elif(Box<IfStat>),
+    Else(StatSeq),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssignOp {
+    Define, // :=
+    Assign, // =
+    Add, Sub, Mul, Div, // +=, -=, *=, /=
+}
+
+impl AssignOp {
+    pub fn from_symbol(op: &str) -> Option<Self> {
+        Some(match op {
+            ":=" => Self::Define,
+            "="  => Self::Assign,
+            "+=" => Self::Add,
+            "-=" => Self::Sub,
+            "*=" => Self::Mul,
+            "/=" => Self::Div,
+            _ => return None,
+        })
+    }
+
+    //the binary operator a compound assignment applies, f.e. `+` for `+=`
+    pub fn binary_op(&self) -> Option<&'static str> {
+        match self {
+            Self::Add => Some("+"),
+            Self::Sub => Some("-"),
+            Self::Mul => Some("*"),
+            Self::Div => Some("/"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AssignOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Define => write!(f, ":="),
+            Self::Assign => write!(f, "="),
+            Self::Add    => write!(f, "+="),
+            Self::Sub    => write!(f, "-="),
+            Self::Mul    => write!(f, "*="),
+            Self::Div    => write!(f, "/="),
+        }
+    }
+}
+
+
+
+
+//walks the tree without modifying it.
+//the interpreter itself matches on the tree directly, this is for tooling.
+//override the `visit_*` methods you are interested in,
+//and call the matching `walk_*` function to keep descending.
+pub trait Visitor {
+    fn visit_block(&mut self, block: &StatSeq) { walk_block(self, block) }
+    fn visit_stmt (&mut self, stmt:  &Stmt)    { walk_stmt (self, stmt)  }
+    fn visit_expr (&mut self, expr:  &Expr)    { walk_expr (self, expr)  }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &StatSeq) {
+    for stmt in &block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+fn walk_if<V: Visitor + ?Sized>(visitor: &mut V, stat: &IfStat) {
+    visitor.visit_expr(&stat.condition);
+    visitor.visit_block(&stat.if_block);
+    match &stat.else_block {
+        Some(ElseBlock::Elif(x)) => walk_if(visitor, x),
+        Some(ElseBlock::Else(x)) => visitor.visit_block(x),
+        None => {},
+    }
+}
+
+pub fn walk_stmt<V: Visitor + ?Sized>(visitor: &mut V, stmt: &Stmt) {
+    match &stmt.kind {
+        StmtKind::Import { .. } => {},
+        StmtKind::Assign { expr, .. } => visitor.visit_expr(expr),
+        StmtKind::Return { expr, .. } => if let Some(x) = expr { visitor.visit_expr(x) },
+        StmtKind::Fun(x) => {
+            for default in x.args.iter().filter_map(|x| x.default.as_ref()) {
+                visitor.visit_expr(default);
+            }
+            visitor.visit_block(&x.body);
+        },
+        StmtKind::Expr(x) => visitor.visit_expr(x),
+        StmtKind::If(x) => walk_if(visitor, x),
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr(condition);
+            visitor.visit_block(body);
+        },
+        StmtKind::For { array, body, .. } => {
+            visitor.visit_expr(array);
+            visitor.visit_block(body);
+        },
+        StmtKind::Try { body, catch, finally } => {
+            visitor.visit_block(body);
+            if let Some(x) = catch { visitor.visit_block(&x.body) }
+            if let Some(x) = finally { visitor.visit_block(x) }
+        },
+        StmtKind::Error => {},
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match &expr.kind {
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        },
+        ExprKind::Unary { operand, .. } | ExprKind::Propagate(operand) => visitor.visit_expr(operand),
+        ExprKind::Format(parts) => for x in parts { visitor.visit_expr(x) },
+        ExprKind::Call(call) | ExprKind::ModAccess { member: call, .. } =>
+            for x in &call.args { visitor.visit_expr(x) },
+        ExprKind::Array(elem) => for x in elem { visitor.visit_expr(x) },
+        ExprKind::Int { .. } | ExprKind::Float { .. } | ExprKind::Str(_) |
+        ExprKind::Variable(_) | ExprKind::Error => {},
+    }
+}
+
+
+
+
+//same as `Visitor`, but is allowed to change the tree in place.
+//function bodies are shared with the executor once it runs,
+//so this only works on a tree that hasn't been executed yet.
+pub trait VisitorMut {
+    fn visit_block_mut(&mut self, block: &mut StatSeq) { walk_block_mut(self, block) }
+    fn visit_stmt_mut (&mut self, stmt:  &mut Stmt)    { walk_stmt_mut (self, stmt)  }
+    fn visit_expr_mut (&mut self, expr:  &mut Expr)    { walk_expr_mut (self, expr)  }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut StatSeq) {
+    for stmt in &mut block.stmts {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+fn walk_if_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stat: &mut IfStat) {
+    visitor.visit_expr_mut(&mut stat.condition);
+    visitor.visit_block_mut(&mut stat.if_block);
+    match &mut stat.else_block {
+        Some(ElseBlock::Elif(x)) => walk_if_mut(visitor, x),
+        Some(ElseBlock::Else(x)) => visitor.visit_block_mut(x),
+        None => {},
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut + ?Sized>(visitor: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Import { .. } => {},
+        StmtKind::Assign { expr, .. } => visitor.visit_expr_mut(expr),
+        StmtKind::Return { expr, .. } => if let Some(x) = expr { visitor.visit_expr_mut(x) },
+        StmtKind::Fun(x) => {
+            //a declaration the executor still holds gets copied, so what it runs stays as it was
+            let decl = Rc::make_mut(x);
+            for default in decl.args.iter_mut().filter_map(|x| x.default.as_mut()) {
+                visitor.visit_expr_mut(default);
+            }
+            visitor.visit_block_mut(&mut decl.body);
+        },
+        StmtKind::Expr(x) => visitor.visit_expr_mut(x),
+        StmtKind::If(x) => walk_if_mut(visitor, x),
+        StmtKind::While { condition, body } => {
+            visitor.visit_expr_mut(condition);
+            visitor.visit_block_mut(body);
+        },
+        StmtKind::For { array, body, .. } => {
+            visitor.visit_expr_mut(array);
+            visitor.visit_block_mut(body);
+        },
+        StmtKind::Try { body, catch, finally } => {
+            visitor.visit_block_mut(body);
+            if let Some(x) = catch { visitor.visit_block_mut(&mut x.body) }
+            if let Some(x) = finally { visitor.visit_block_mut(x) }
+        },
+        StmtKind::Error => {},
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match &mut expr.kind {
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr_mut(left);
+            visitor.visit_expr_mut(right);
+        },
+        ExprKind::Unary { operand, .. } | ExprKind::Propagate(operand) => visitor.visit_expr_mut(operand),
+        ExprKind::Format(parts) => for x in parts { visitor.visit_expr_mut(x) },
+        ExprKind::Call(call) | ExprKind::ModAccess { member: call, .. } =>
+            for x in &mut call.args { visitor.visit_expr_mut(x) },
+        ExprKind::Array(elem) => for x in elem { visitor.visit_expr_mut(x) },
+        ExprKind::Int { .. } | ExprKind::Float { .. } | ExprKind::Str(_) |
+        ExprKind::Variable(_) | ExprKind::Error => {},
+    }
+}
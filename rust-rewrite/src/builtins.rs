@@ -0,0 +1,285 @@
+
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::rc::Rc;
+
+use crate::executor::{call_function, catch, error, new_error, raise, truthiness, Obj, RuntimeError, Scope};
+use crate::executor::{ERR_ARGS, ERR_ASSERT, ERR_NAME, ERR_SYSTEM, ERR_THROWN, ERR_TYPE};
+use crate::executor::{ERR_INVALID_DATA, ERR_INVALID_NUMBER, ERR_NOT_FOUND, ERR_PERMISSION};
+
+
+//the built-in modules, like `io` or `prog`.
+//each member is a plain rust function that gets its arguments already evaluated.
+//misuse, f.e. a wrong type of argument, is raised. a failure the program can expect,
+//f.e. a missing file, is returned as an error value instead, see `fails`.
+
+pub struct Member {
+    pub name: &'static str,
+    pub arity: Option<usize>, //`None` takes any number of arguments
+    pub call: fn(&mut Scope, Vec<Obj>) -> Obj,
+    pub params: &'static str, //how editors show the arguments, f.e. `name`
+    pub doc: &'static str,
+}
+
+pub struct Module {
+    pub name: &'static str,
+    pub members: &'static [Member],
+}
+
+
+pub const MODULES: &[Module] = &[
+    Module { name: "io", members: &[
+        Member { name: "println",  arity: None,    call: io_println,   params: "...values",
+                 doc: "Prints the values on one line, separated by spaces." },
+        Member { name: "readTextFile", arity: Some(1), call: io_read_text_file, params: "path",
+                 doc: "The contents of the file, or an error, f.e. of code `not-found` or `permission-denied`." },
+    ]},
+    Module { name: "tu", members: &[
+        Member { name: "toString", arity: Some(1), call: tu_to_string, params: "value",
+                 doc: "The value as a string, the way `io::println` prints it." },
+        Member { name: "getNil",   arity: Some(0), call: tu_get_nil,   params: "",
+                 doc: "Nil, which has no literal of its own." },
+        Member { name: "toNumber", arity: Some(1), call: tu_to_number, params: "value",
+                 doc: "The number a string holds, or an error of code `invalid-number`. Numbers stay as they are, Nil is 0." },
+        Member { name: "isError",  arity: Some(1), call: tu_is_error,  params: "value",
+                 doc: "Whether the value is an error, f.e. one a builtin returned." },
+        Member { name: "makeError",    arity: None,    call: tu_make_error,    params: "message, code, cause",
+                 doc: "A new error value with the message, the code if given (`thrown` otherwise) and the error that caused it if given." },
+        Member { name: "errorMessage", arity: Some(1), call: tu_error_message, params: "error",
+                 doc: "What went wrong, f.e. `Unable to divide an integer by zero.`" },
+        Member { name: "errorCode",    arity: Some(1), call: tu_error_code,    params: "error",
+                 doc: "The kind of error, f.e. `type`, `name`, `division-by-zero` or the code given to `prog::throw`." },
+        Member { name: "errorLine",    arity: Some(1), call: tu_error_line,    params: "error",
+                 doc: "The line the error happened on." },
+        Member { name: "errorCause",   arity: Some(1), call: tu_error_cause,   params: "error",
+                 doc: "The error that caused this one, or Nil if it has none." },
+    ]},
+    Module { name: "prog", members: &[
+        Member { name: "args",     arity: Some(0), call: prog_args,    params: "",
+                 doc: "The arguments given after the program, as an array of strings." },
+        Member { name: "env",      arity: Some(1), call: prog_env,     params: "name",
+                 doc: "The environment variable `name`, or Nil if it isn't set." },
+        Member { name: "cwd",      arity: Some(0), call: prog_cwd,     params: "",
+                 doc: "The current working directory." },
+        Member { name: "throw",    arity: None,    call: prog_throw,   params: "message, code",
+                 doc: "Raises an error with the message, and the code if given (`thrown` otherwise). \
+                       Given a caught error instead, raises it again as it was." },
+    ]},
+    Module { name: "assert", members: &[
+        Member { name: "equal",    arity: Some(2), call: assert_equal,     params: "actual, expected",
+                 doc: "Fails unless the values are equal, showing how they differ." },
+        Member { name: "notEqual", arity: Some(2), call: assert_not_equal, params: "actual, unexpected",
+                 doc: "Fails if the values are equal." },
+        Member { name: "isTrue",   arity: Some(1), call: assert_is_true,   params: "value",
+                 doc: "Fails unless the value counts as true, the way an `if` would take it." },
+        Member { name: "throws",   arity: None,    call: assert_throws,    params: "name, ...args",
+                 doc: "Calls the function `name` with the arguments and fails unless it ends in a runtime error. \
+                       Returns the message of the error." },
+    ]},
+];
+
+pub fn lookup(mod_name: &str, name: &str) -> Option<&'static Member> {
+    let module = MODULES.iter().find(|x| x.name == mod_name)?;
+    module.members.iter().find(|x| x.name == name)
+}
+
+
+//an error value for the program to handle, it happened where the builtin was called
+fn fails(code: &str, msg: String) -> Obj {
+    Obj::Error(Rc::new(new_error(code, msg)))
+}
+
+
+fn io_println(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    let line: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+    println!("{}", line.join(" "));
+    Obj::Nil
+}
+
+fn io_read_text_file(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    let Obj::String(ref path) = args[0] else {
+        error(ERR_TYPE, "Function readTextFile only accepts a string as the path.".to_string());
+    };
+    match fs::read_to_string(path) {
+        Ok(x) => Obj::String(x),
+        Err(x) => match x.kind() {
+            ErrorKind::NotFound         => fails(ERR_NOT_FOUND, format!("There is no file '{}'.", path)),
+            ErrorKind::PermissionDenied => fails(ERR_PERMISSION, format!("Not allowed to read file '{}'.", path)),
+            ErrorKind::InvalidData      => fails(ERR_INVALID_DATA, format!("File '{}' isn't valid UTF-8 text.", path)),
+            _                           => fails(ERR_SYSTEM, format!("Unable to read file '{}': {}.", path, x)),
+        },
+    }
+}
+
+
+fn tu_to_string(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    Obj::String(args[0].to_string())
+}
+
+fn tu_get_nil(_: &mut Scope, _: Vec<Obj>) -> Obj {
+    Obj::Nil
+}
+
+fn tu_to_number(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    match &args[0] {
+        Obj::Int(_) | Obj::Float(_) => args[0].clone(),
+        Obj::Nil => Obj::Int(0),
+        Obj::String(x) => {
+            let text = x.trim();
+            if let Ok(x) = text.parse::<i64>() { return Obj::Int(x) }
+            match text.parse::<f64>() {
+                Ok(x) if x.is_finite() => Obj::Float(x),
+                _ => fails(ERR_INVALID_NUMBER, format!("Unable to read a number from {:?}.", x)),
+            }
+        },
+        x => error(ERR_TYPE, format!("Function toNumber only accepts a string or a number, but got {:?}.", x)),
+    }
+}
+
+fn tu_is_error(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    Obj::Bool(matches!(args[0], Obj::Error(_)))
+}
+
+fn tu_make_error(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    let (msg, code, cause) = match args.as_slice() {
+        [Obj::String(msg)] => (msg, ERR_THROWN, None),
+        [Obj::String(msg), Obj::String(code)] => (msg, code.as_str(), None),
+        [Obj::String(msg), Obj::String(code), Obj::Error(cause)] => (msg, code.as_str(), Some(cause.clone())),
+        _ => error(ERR_TYPE, "Function makeError expects a message, optionally a code, both strings, and then optionally the error that caused it.".to_string()),
+    };
+    let mut new = new_error(code, msg.clone());
+    new.cause = cause;
+    Obj::Error(Rc::new(new))
+}
+
+fn error_arg(function: &str, obj: &Obj) -> Rc<RuntimeError> {
+    let Obj::Error(x) = obj else {
+        error(ERR_TYPE, format!("Function {} only accepts an error, but got {:?}.", function, obj));
+    };
+    x.clone()
+}
+
+fn tu_error_message(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    Obj::String(error_arg("errorMessage", &args[0]).msg.clone())
+}
+
+fn tu_error_code(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    Obj::String(error_arg("errorCode", &args[0]).code.clone())
+}
+
+fn tu_error_line(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    Obj::Int(error_arg("errorLine", &args[0]).line as i64)
+}
+
+fn tu_error_cause(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    match &error_arg("errorCause", &args[0]).cause {
+        Some(x) => Obj::Error(x.clone()),
+        None => Obj::Nil,
+    }
+}
+
+
+//the arguments after the program, f.e. `["a", "b"]` for `oko run tool.oko a b`
+fn prog_args(scope: &mut Scope, _: Vec<Obj>) -> Obj {
+    Obj::Array(scope.args.iter().map(|x| Obj::String(x.clone())).collect())
+}
+
+fn prog_env(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    let Obj::String(ref name) = args[0] else {
+        error(ERR_TYPE, "Function env only accepts a string as the variable name.".to_string());
+    };
+    match env::var(name) {
+        Ok(x) => Obj::String(x),
+        Err(_) => Obj::Nil, //unset, or not valid unicode
+    }
+}
+
+fn prog_cwd(_: &mut Scope, _: Vec<Obj>) -> Obj {
+    let Ok(path) = env::current_dir() else {
+        error(ERR_SYSTEM, "Unable to determine the current working directory.".to_string());
+    };
+    Obj::String(path.to_string_lossy().into_owned())
+}
+
+fn prog_throw(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    match args.as_slice() {
+        [Obj::Error(x)] => raise((**x).clone()),
+        [Obj::String(msg)] => error(ERR_THROWN, msg.clone()),
+        [Obj::String(msg), Obj::String(code)] => error(code, msg.clone()),
+        _ => error(ERR_TYPE, "Function throw expects a message and optionally a code, both strings, or a caught error.".to_string()),
+    }
+}
+
+
+//the lines of both, the ones only in `expected` marked with `-`, the ones only in `actual` with `+`
+fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<&str> = expected.lines().collect();
+    let b: Vec<&str> = actual.lines().collect();
+
+    //common[i][j] is how many lines `a` from `i` on and `b` from `j` on have in common
+    let mut common = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] { common[i + 1][j + 1] + 1 } else { common[i + 1][j].max(common[i][j + 1]) };
+        }
+    }
+
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out += &format!("\n      {}", a[i]);
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            out += &format!("\n    - {}", a[i]);
+            i += 1;
+        } else {
+            out += &format!("\n    + {}", b[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+//a failed assertion is a runtime error, so it stops the test it is in
+fn assert_equal(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    let (actual, expected) = (&args[0], &args[1]);
+    if actual == expected { return Obj::Nil }
+
+    let (actual, expected) = (format!("{:#?}", actual), format!("{:#?}", expected));
+    if !actual.contains('\n') && !expected.contains('\n') {
+        error(ERR_ASSERT, format!("Assertion failed, expected {}, but got {}.", expected, actual));
+    }
+    error(ERR_ASSERT, format!("Assertion failed, the values differ (- expected, + actual):{}", diff(&expected, &actual)));
+}
+
+fn assert_not_equal(_: &mut Scope, args: Vec<Obj>) -> Obj {
+    if args[0] != args[1] { return Obj::Nil }
+    error(ERR_ASSERT, format!("Assertion failed, expected the values to differ, but both are {:?}.", args[0]));
+}
+
+fn assert_is_true(_: &mut Scope, mut args: Vec<Obj>) -> Obj {
+    let value = args.remove(0);
+    if truthiness(value.clone()) { return Obj::Nil }
+    error(ERR_ASSERT, format!("Assertion failed, expected a true value, but got {:?}.", value));
+}
+
+fn assert_throws(scope: &mut Scope, mut args: Vec<Obj>) -> Obj {
+    if args.is_empty() {
+        error(ERR_ARGS, "Function throws expects the name of a function to call.".to_string());
+    }
+    let Obj::String(name) = args.remove(0) else {
+        error(ERR_TYPE, "Function throws only accepts a string as the function name.".to_string());
+    };
+    //otherwise calling it would fail, and count as the error that was expected
+    if !scope.funs.contains_key(&name) {
+        error(ERR_NAME, format!("Function of name {} is not declared in scope.", name));
+    }
+
+    match catch(|| call_function(scope, &name, args, 0)) {
+        Ok(value) => error(ERR_ASSERT, format!("Assertion failed, expected {} to fail, but it returned {:?}.", name, value)),
+        Err(x) => Obj::String(x.msg),
+    }
+}
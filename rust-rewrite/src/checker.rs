@@ -0,0 +1,458 @@
+
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast::{self, ElseBlock, Expr, ExprKind, FunctionCall, FunctionDeclare, StatSeq, Stmt, StmtKind, Visitor};
+use crate::builtins;
+use crate::executor::describe_arity;
+use crate::lexer::{Diagnostic, Span};
+
+
+//the static checks behind `oko check`: undefined variables, undeclared functions,
+//variables defined twice, `return` outside of a function, unknown modules and wrong argument counts.
+//
+//the interpreter hands every call the scope of its caller, so strictly speaking
+//a name can only be resolved at runtime. the checker goes by where code is written instead:
+// - code sees what was defined before it in its own function (or the top level of the program),
+// - and everything defined anywhere in the functions around it, since it runs after they were set up.
+//`if`, `while`, `try` and `finally` blocks don't open a scope, their definitions stay visible after them.
+//a `:=` is only checked against the scope it really runs in though: what its function defined so far,
+//and whatever the code calling the function had defined at any of its calls, f.e. the function itself
+//when it recurses after a `:=`.
+//along the way it records what every name refers to, for the language server.
+
+
+#[derive(PartialEq)]
+enum ScopeKind {
+    Program,
+    Function(usize), //the offset of its declaration
+    Loop,            //the body of a `for`, or of a `catch` along with its error
+}
+
+//where a name was defined
+#[derive(Debug, Clone)]
+pub enum Definition {
+    Var(Span),                      //the name of a `:=`, parameter, `for` element or caught error
+    Fun(Span, Rc<FunctionDeclare>), //the whole `fun` statement
+}
+
+//a variable or function name in the source, and what it refers to
+#[derive(Debug)]
+pub struct Reference {
+    pub span: Span,
+    pub definition: Definition,
+}
+
+//everything `check` found out about a program
+pub struct Analysis {
+    pub diagnostics: Vec<Diagnostic>, //in source order
+    pub references: Vec<Reference>,
+}
+
+
+type Fun = (Span, Rc<FunctionDeclare>);
+
+//a call of a declared function, with the variables the caller had defined by then.
+//functions are told apart by the offset of their declaration
+struct Call {
+    caller: Option<usize>, //`None` at the top level
+    callee: usize,
+    line: u32,
+    defined: Vec<String>,
+}
+
+//a `:=` in a function, which fails if a caller defined the variable already
+struct Define {
+    function: usize,
+    name: String,
+    span: Span,
+}
+
+struct Scope {
+    kind: ScopeKind,
+    vars: HashMap<String, Span>,       //defined so far
+    funs: HashMap<String, Fun>,        //declared so far
+    all_vars: HashMap<String, Span>,   //defined anywhere in the scope
+    all_funs: HashMap<String, Fun>,
+}
+
+impl Scope {
+    fn new(kind: ScopeKind, block: &StatSeq) -> Self {
+        let mut scope = Scope { kind, vars: HashMap::new(), funs: HashMap::new(), all_vars: HashMap::new(), all_funs: HashMap::new() };
+        scope.collect(block);
+        scope
+    }
+
+    //everything a block defines into this scope, without looking into nested functions or loops
+    fn collect(&mut self, block: &StatSeq) {
+        for stmt in &block.stmts {
+            match &stmt.kind {
+                StmtKind::Assign { var_name, op: ast::AssignOp::Define, .. } => {
+                    self.all_vars.insert(var_name.clone(), Span { len: var_name.len(), ..stmt.span });
+                },
+                StmtKind::Fun(decl) => { self.all_funs.insert(decl.name.clone(), (stmt.span, decl.clone())); },
+                StmtKind::While { body, .. } => self.collect(body),
+                StmtKind::Try { body, finally, .. } => {
+                    self.collect(body);
+                    if let Some(x) = finally { self.collect(x) }
+                },
+                StmtKind::If(stat) => {
+                    let mut next = Some(stat);
+                    while let Some(stat) = next {
+                        self.collect(&stat.if_block);
+                        next = match &stat.else_block {
+                            Some(ElseBlock::Elif(x)) => Some(x),
+                            Some(ElseBlock::Else(x)) => { self.collect(x); None },
+                            None => None,
+                        };
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    fn define(&mut self, name: &str, span: Span) {
+        self.vars.insert(name.to_string(), span);
+        self.all_vars.entry(name.to_string()).or_insert(span);
+    }
+}
+
+
+struct Checker {
+    scopes: Vec<Scope>,
+    diagnostics: Vec<Diagnostic>,
+    references: Vec<Reference>,
+    calls: Vec<Call>,
+    defines: Vec<Define>,
+}
+
+impl Checker {
+    fn error(&mut self, span: Span, msg: String) {
+        self.diagnostics.push(Diagnostic { span, msg });
+    }
+
+    //scopes up to the innermost function are only searched for what was defined so far,
+    //the ones further out completely
+    fn lookup<T>(&self, find: impl Fn(&Scope, bool) -> Option<T>) -> Option<T> {
+        let mut so_far = true;
+        for scope in self.scopes.iter().rev() {
+            if let Some(x) = find(scope, so_far) { return Some(x) }
+            if scope.kind != ScopeKind::Loop { so_far = false; }
+        }
+        None
+    }
+
+    fn find_var(&self, name: &str) -> Option<Span> {
+        self.lookup(|scope, so_far| {
+            let vars = if so_far { &scope.vars } else { &scope.all_vars };
+            vars.get(name).copied()
+        })
+    }
+
+    fn find_fun(&self, name: &str) -> Option<Fun> {
+        self.lookup(|scope, so_far| {
+            let funs = if so_far { &scope.funs } else { &scope.all_funs };
+            funs.get(name).cloned()
+        })
+    }
+
+    fn current(&mut self) -> &mut Scope {
+        self.scopes.last_mut().unwrap()
+    }
+
+    //the scopes a call or a `:=` shares at runtime, up to the innermost function or the top level
+    fn local_scopes(&self) -> impl Iterator<Item = &Scope> {
+        let outer = self.scopes.iter().rposition(|x| x.kind != ScopeKind::Loop).unwrap_or(0);
+        self.scopes[outer..].iter()
+    }
+
+    //the function the code is in, `None` at the top level
+    fn function(&self) -> Option<usize> {
+        return match self.local_scopes().next().map(|x| &x.kind) {
+            Some(ScopeKind::Function(offset)) => Some(*offset),
+            _ => None,
+        };
+    }
+
+    fn check_define(&mut self, name: &str, span: Span) {
+        if self.local_scopes().any(|x| x.vars.contains_key(name)) {
+            self.error(span, format!("Variable of name {} is already defined in scope.", name));
+            return;
+        }
+        //the callers are only all known at the end, see `check_inherited`
+        if let Some(function) = self.function() {
+            self.defines.push(Define { function, name: name.to_string(), span });
+        }
+    }
+
+    //the variables every function finds defined when it is called, along with the line of such a call.
+    //a caller passes on what it got from its own callers too, so this goes on until nothing is added
+    fn inherited(&self) -> HashMap<usize, HashMap<String, u32>> {
+        let mut inherited: HashMap<usize, HashMap<String, u32>> = HashMap::new();
+        loop {
+            let mut changed = false;
+            for call in &self.calls {
+                let mut names: HashSet<&String> = call.defined.iter().collect();
+                let from_caller = call.caller.and_then(|x| inherited.get(&x)).cloned().unwrap_or_default();
+                names.extend(from_caller.keys());
+
+                let callee = inherited.entry(call.callee).or_default();
+                for name in names {
+                    if !callee.contains_key(name) {
+                        callee.insert(name.clone(), call.line);
+                        changed = true;
+                    }
+                }
+            }
+            if !changed { return inherited }
+        }
+    }
+
+    fn check_inherited(&mut self) {
+        let inherited = self.inherited();
+        for define in std::mem::take(&mut self.defines) {
+            let Some(line) = inherited.get(&define.function).and_then(|x| x.get(&define.name)) else { continue };
+            self.error(define.span, format!(
+                "Variable of name {} is already defined in scope when the function is called at line {}.",
+                define.name, line,
+            ));
+        }
+    }
+
+    fn check_var(&mut self, name: &str, span: Span) {
+        let Some(definition) = self.find_var(name) else {
+            self.error(span, format!("Variable of name {} is not defined in scope.", name));
+            return;
+        };
+        self.references.push(Reference { span, definition: Definition::Var(definition) });
+    }
+
+    fn check_call(&mut self, call: &FunctionCall, span: Span) {
+        let name_span = Span { len: call.name.len(), ..span };
+        let Some((decl_span, decl)) = self.find_fun(&call.name) else {
+            self.error(name_span, format!("Function of name {} is not declared in scope.", call.name));
+            return;
+        };
+        self.references.push(Reference { span: name_span, definition: Definition::Fun(decl_span, decl.clone()) });
+        let defined = self.local_scopes().flat_map(|x| x.vars.keys().cloned()).collect();
+        self.calls.push(Call { caller: self.function(), callee: decl_span.offset, line: span.line, defined });
+
+        let required = decl.args.iter().filter(|x| x.default.is_none()).count();
+        if call.args.len() < required || (decl.rest.is_none() && call.args.len() > decl.args.len()) {
+            self.error(span, format!(
+                "Function {} expects {} argument(s), but got {}.",
+                call.name, describe_arity(&decl), call.args.len(),
+            ));
+        }
+    }
+
+    fn check_mod_access(&mut self, mod_name: &str, member: &FunctionCall, span: Span) {
+        if !builtins::MODULES.iter().any(|x| x.name == mod_name) {
+            self.error(Span { len: mod_name.len(), ..span }, format!("Module {} does not exist.", mod_name));
+            return;
+        }
+        let Some(builtin) = builtins::lookup(mod_name, &member.name) else {
+            self.error(span, format!("Module {} has no function named {}.", mod_name, member.name));
+            return;
+        };
+
+        if let Some(arity) = builtin.arity {
+            if member.args.len() != arity {
+                self.error(span, format!(
+                    "Function {}::{} expects {} argument(s), but got {}.",
+                    mod_name, member.name, arity, member.args.len(),
+                ));
+            }
+        }
+    }
+
+    fn check_fun(&mut self, span: Span, decl: &FunctionDeclare) {
+        self.scopes.push(Scope::new(ScopeKind::Function(span.offset), &decl.body));
+
+        //defaults are evaluated inside of the call, after the parameters before them
+        for param in &decl.args {
+            if let Some(default) = &param.default {
+                self.visit_expr(default);
+            }
+            self.current().define(&param.name, param.span);
+        }
+        if let Some(rest) = &decl.rest {
+            self.current().define(&rest.name, rest.span);
+        }
+
+        self.visit_block(&decl.body);
+        self.scopes.pop();
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Import { mod_name } => {
+                if !builtins::MODULES.iter().any(|x| x.name == mod_name) {
+                    self.error(stmt.span, format!("Module {} does not exist.", mod_name));
+                }
+            },
+            StmtKind::Assign { var_name, op, expr } => {
+                if *op != ast::AssignOp::Define {
+                    self.check_var(var_name, Span { len: var_name.len(), ..stmt.span });
+                }
+                self.visit_expr(expr);
+                if *op == ast::AssignOp::Define {
+                    let span = Span { len: var_name.len(), ..stmt.span };
+                    self.check_define(var_name, span);
+                    self.references.push(Reference { span, definition: Definition::Var(span) });
+                    self.current().define(var_name, span);
+                }
+            },
+            StmtKind::Return { expr, .. } => {
+                if !self.scopes.iter().any(|x| matches!(x.kind, ScopeKind::Function(_))) {
+                    self.error(Span { len: "return".len(), ..stmt.span }, "Return can only be used inside of a function.".to_string());
+                }
+                if let Some(x) = expr { self.visit_expr(x); }
+            },
+            StmtKind::Fun(decl) => {
+                //declared before its body is checked, so it can call itself
+                self.current().funs.insert(decl.name.clone(), (stmt.span, decl.clone()));
+                self.check_fun(stmt.span, decl);
+            },
+            StmtKind::For { elem_name, elem_span, array, body } => {
+                self.visit_expr(array);
+                self.scopes.push(Scope::new(ScopeKind::Loop, body));
+                self.current().define(elem_name, *elem_span);
+                self.visit_block(body);
+                self.scopes.pop();
+            },
+            //only one of the branches runs, what the others define isn't there yet
+            StmtKind::If(stat) => {
+                let before = self.current().vars.clone();
+                let mut after = before.clone();
+                let mut next = Some(stat);
+                while let Some(stat) = next {
+                    self.current().vars = before.clone();
+                    self.visit_expr(&stat.condition);
+                    self.visit_block(&stat.if_block);
+                    after.extend(std::mem::take(&mut self.current().vars));
+                    next = match &stat.else_block {
+                        Some(ElseBlock::Elif(x)) => Some(x),
+                        Some(ElseBlock::Else(x)) => {
+                            self.current().vars = before.clone();
+                            self.visit_block(x);
+                            after.extend(std::mem::take(&mut self.current().vars));
+                            None
+                        },
+                        None => None,
+                    };
+                }
+                self.current().vars = after;
+            },
+            StmtKind::Try { body, catch, finally } => {
+                self.visit_block(body);
+                if let Some(catch) = catch {
+                    self.scopes.push(Scope::new(ScopeKind::Loop, &catch.body));
+                    self.current().define(&catch.name, catch.span);
+                    self.visit_block(&catch.body);
+                    self.scopes.pop();
+                }
+                if let Some(x) = finally { self.visit_block(x) }
+            },
+            _ => ast::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.check_var(name, expr.span),
+            ExprKind::Call(call) => self.check_call(call, expr.span),
+            ExprKind::ModAccess { mod_name, member } => self.check_mod_access(mod_name, member, expr.span),
+            _ => {},
+        }
+        ast::walk_expr(self, expr);
+    }
+}
+
+
+//checks a program without syntax errors
+pub fn check(root: &StatSeq) -> Analysis {
+    let mut checker = Checker {
+        scopes: vec![Scope::new(ScopeKind::Program, root)], diagnostics: vec![], references: vec![],
+        calls: vec![], defines: vec![],
+    };
+    checker.visit_block(root);
+    checker.check_inherited();
+
+    let mut diagnostics = checker.diagnostics;
+    diagnostics.sort_by_key(|x| x.span.offset);
+    Analysis { diagnostics, references: checker.references }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn errors(source: &str) -> Vec<(u32, String)> {
+        let mut stream = lexer::lex(source);
+        let root = parser::parse_program(&mut stream);
+        assert!(stream.diagnostics().is_empty(), "{:?}", stream.diagnostics());
+        check(&root).diagnostics.into_iter().map(|x| (x.span.line, x.msg)).collect()
+    }
+
+    #[test]
+    fn undefined_names() {
+        assert_eq!(errors("x := y;\nf();\nimport nope;\nimport io;\nio::nope();"), vec![
+            (1, "Variable of name y is not defined in scope.".to_string()),
+            (2, "Function of name f is not declared in scope.".to_string()),
+            (3, "Module nope does not exist.".to_string()),
+            (5, "Module io has no function named nope.".to_string()),
+        ]);
+
+        //defined later in the function around it, or only in a `for` or `catch` block
+        assert!(errors("fun f() { return g() + x; }\nfun g() { return 1; }\nx := 1;\nf();").is_empty());
+        assert_eq!(errors("for (x) ([1]) {}\ny := x;")[0], (2, "Variable of name x is not defined in scope.".to_string()));
+        assert_eq!(errors("try {} catch (e) {}\ny := e;")[0], (2, "Variable of name e is not defined in scope.".to_string()));
+    }
+
+    #[test]
+    fn argument_counts() {
+        let source = "import io;\nfun f(a, b := 1, ...rest) {}\nfun g(a) {}\nf();\nf(1, 2, 3, 4);\ng(1, 2);\nio::readTextFile(1, 2);";
+        assert_eq!(errors(source), vec![
+            (4, "Function f expects at least 1 argument(s), but got 0.".to_string()),
+            (6, "Function g expects 1 argument(s), but got 2.".to_string()),
+            (7, "Function io::readTextFile expects 1 argument(s), but got 2.".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn return_outside_of_a_function() {
+        assert_eq!(errors("return 1;\nfun f() { if (1) { return 2; } }"), vec![
+            (1, "Return can only be used inside of a function.".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn variables_defined_twice() {
+        assert_eq!(errors("x := 1;\nx := 2;\nfun f(a) { a := 1; }"), vec![
+            (2, "Variable of name x is already defined in scope.".to_string()),
+            (3, "Variable of name a is already defined in scope.".to_string()),
+        ]);
+
+        //a call sees the variables of its caller
+        assert_eq!(errors("x := 1;\nfun f() { x := 2; }\nf();"), vec![
+            (2, "Variable of name x is already defined in scope when the function is called at line 3.".to_string()),
+        ]);
+        assert_eq!(errors("fun f(n) {\n    y := n;\n    if (n > 0) { f(n - 1); }\n}\nf(3);"), vec![
+            (2, "Variable of name y is already defined in scope when the function is called at line 3.".to_string()),
+        ]);
+        assert_eq!(errors("fun f() { z := 1; }\nfun g() { z := 1; f(); }\nfun h() { g(); }\nz := 0;\nh();"), vec![
+            (1, "Variable of name z is already defined in scope when the function is called at line 2.".to_string()),
+            (2, "Variable of name z is already defined in scope when the function is called at line 3.".to_string()),
+        ]);
+
+        //only one branch of an `if` runs, a `for` starts over every time, and a call doesn't define anything for its caller
+        let source = "if (1) { x := 1; } elif (0) { x := 2; } else { x := 3; }\nfor (e) ([1, 2]) { y := e; }\nfun f(e) { y := e; }\nf(1);\nf(2);\ny := 1;";
+        assert!(errors(source).is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+
+//command line handling, everything `main` needs to know about its arguments.
+
+
+pub const USAGE: &str = "\
+Usage: oko [run] [options] <file> [args...]   run a program
+       oko [run] [options] -e <code> [args...] run a one-liner
+       oko [run] [options] - [args...]         run a program read from stdin
+       oko check <file>                        check a program for undefined names and misuse without running it
+       oko fmt [--check] <file>...             format programs in place, `-` and `-e` print to stdout
+       oko debug <file> [args...]              run a program in the debugger, commands are read from stdin
+       oko test [<path>...]                    run the `test_*` functions of `*_test.oko` files, the ones in
+                                               the working directory and below it by default
+       oko repl                                start an interactive session
+       oko lsp [--stdio]                       run the language server for editors over stdin and stdout
+       oko license                             show license information
+       oko --version                           show the interpreter version
+
+Options for `run`:
+       --dump-tokens         print the token stream instead of running
+       --dump-ast[=tree]     print the syntax tree as an indented outline instead of running
+       --dump-ast=json       print the syntax tree as json instead of running
+       --max-call-depth=<n>  how deeply functions may call each other, 10000 by default
+       --profile[=<file>]    print the time spent per function and line to stderr, and write the time
+                             per call stack for flamegraph tools to a file, `profile.folded` by default
+       --coverage[=<file>]   print which statements and branches ran to stderr, and write the details
+                             in the lcov format to a file, `lcov.info` by default
+
+Options for `fmt`:
+       --check               only report programs that aren't formatted, and fail if there are any
+
+Warnings of `check` can be turned off per rule, with `<rule> = off` lines in a `.okolint` file next to the program,
+or with `// lint: allow <rule>` (this and the next line) and `// lint: disable|enable <rule>` comments.
+Rules: unused-variable, unused-parameter, shadowed-variable, unreachable-code, constant-condition,
+       unused-import, self-assignment
+
+Exit codes: 0 success, 1 runtime error, failed check or failed test, 2 usage error, 3 syntax error";
+
+pub const LICENSE: &str = "\
+oko-lang: Language of vision
+Copyright (C) 2025 tixonochek
+        This program is free software: you can redistribute it and/or modify
+        it under the terms of the GNU General Public License as published by
+        the Free Software Foundation, either version 3 of the License, or
+        (at your option) any later version.
+
+        This program is distributed in the hope that it will be useful,
+        but WITHOUT ANY WARRANTY; without even the implied warranty of
+        MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+        GNU General Public License for more details.
+
+        You should have received a copy of the GNU General Public License
+        along with this program. If not, see <https://www.gnu.org/licenses/>.";
+
+
+//where the program text comes from
+#[derive(Debug)]
+pub enum Input {
+    File(String),
+    Stdin,      // -
+    Code(String), // -e '<code>'
+}
+
+impl Input {
+    //for messages about the input
+    pub fn name(&self) -> &str {
+        match self {
+            Input::File(x) => x,
+            Input::Stdin   => "<stdin>",
+            Input::Code(_) => "<code>",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Tree,
+    Json,
+}
+
+#[derive(Debug, Default)]
+pub struct RunOptions {
+    pub dump_tokens: bool,
+    pub dump_ast: Option<DumpFormat>,
+    pub max_call_depth: Option<usize>,
+    pub profile: Option<String>, //where the folded stacks go
+    pub coverage: Option<String>, //where the lcov data goes
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Run {
+        input: Input,
+        args: Vec<String>, //everything after the program, it is not interpreted by oko itself
+        options: RunOptions,
+    },
+    Check(Input),
+    Debug {
+        input: Input,
+        args: Vec<String>,
+    },
+    Fmt {
+        inputs: Vec<Input>,
+        check: bool,
+    },
+    Test(Vec<String>), //files and directories
+    Repl,
+    Lsp,
+    License,
+    Version,
+    Help,
+}
+
+
+fn parse_input(arg: String, args: &mut impl Iterator<Item = String>) -> Result<Input, String> {
+    match arg.as_str() {
+        "-"  => Ok(Input::Stdin),
+        "-e" => match args.next() {
+            Some(code) => Ok(Input::Code(code)),
+            None => Err("Option '-e' expects a piece of code to run.".to_string()),
+        },
+        x if x.starts_with('-') => Err(format!("Unknown option '{}'.", x)),
+        _ => Ok(Input::File(arg)),
+    }
+}
+
+fn parse_run(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut options = RunOptions::default();
+
+    //options only count until the program is named, the rest belongs to the program
+    loop {
+        let Some(arg) = args.next() else {
+            return Err("No program to run was provided.".to_string());
+        };
+        match arg.as_str() {
+            "--dump-tokens"    => options.dump_tokens = true,
+            "--dump-ast"       => options.dump_ast = Some(DumpFormat::Tree),
+            "--dump-ast=tree"  => options.dump_ast = Some(DumpFormat::Tree),
+            "--dump-ast=json"  => options.dump_ast = Some(DumpFormat::Json),
+            "--profile" => options.profile = Some("profile.folded".to_string()),
+            x if x.starts_with("--profile=") => options.profile = Some(x["--profile=".len()..].to_string()),
+            "--coverage" => options.coverage = Some("lcov.info".to_string()),
+            x if x.starts_with("--coverage=") => options.coverage = Some(x["--coverage=".len()..].to_string()),
+            x if x.starts_with("--max-call-depth=") => {
+                let value = &x["--max-call-depth=".len()..];
+                let Some(depth) = value.parse().ok().filter(|x| *x > 0) else {
+                    return Err(format!("Option '--max-call-depth' expects a positive number, but got '{}'.", value));
+                };
+                options.max_call_depth = Some(depth);
+            },
+            _ => {
+                //both watch the program through the same hooks
+                if options.profile.is_some() && options.coverage.is_some() {
+                    return Err("Options '--profile' and '--coverage' can't be used together.".to_string());
+                }
+                let input = parse_input(arg, &mut args)?;
+                return Ok(Command::Run { input, args: args.collect(), options });
+            },
+        }
+    }
+}
+
+//the debugger reads its commands from stdin, so the program can't come from there
+fn parse_debug(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let Some(arg) = args.next() else {
+        return Err("`oko debug` expects a program.".to_string());
+    };
+    match parse_input(arg, &mut args)? {
+        Input::Stdin => Err("`oko debug` reads its commands from stdin, the program has to be a file or `-e` code.".to_string()),
+        input => Ok(Command::Debug { input, args: args.collect() }),
+    }
+}
+
+//for subcommands that take exactly one program
+fn parse_single(name: &str, mut args: impl Iterator<Item = String>) -> Result<Input, String> {
+    let Some(arg) = args.next() else {
+        return Err(format!("`oko {}` expects a program.", name));
+    };
+    let input = parse_input(arg, &mut args)?;
+    if let Some(x) = args.next() {
+        return Err(format!("Unexpected argument '{}'.", x));
+    }
+    Ok(input)
+}
+
+fn parse_fmt(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut inputs = vec![];
+    let mut check = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ => inputs.push(parse_input(arg, &mut args)?),
+        }
+    }
+    if inputs.is_empty() {
+        return Err("`oko fmt` expects at least one program.".to_string());
+    }
+    Ok(Command::Fmt { inputs, check })
+}
+
+fn parse_test(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut paths = vec![];
+    for arg in args {
+        if arg.starts_with('-') {
+            return Err(format!("Unknown option '{}'.", arg));
+        }
+        paths.push(arg);
+    }
+    if paths.is_empty() {
+        paths.push(".".to_string());
+    }
+    Ok(Command::Test(paths))
+}
+
+//editors tend to pass `--stdio`, which is the only transport there is anyway
+fn parse_lsp(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    parse_none(Command::Lsp, args.filter(|x| x != "--stdio"))
+}
+
+fn parse_none(command: Command, mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    match args.next() {
+        Some(x) => Err(format!("Unexpected argument '{}'.", x)),
+        None => Ok(command),
+    }
+}
+
+//`args` should not include the path of the executable
+pub fn parse(args: Vec<String>) -> Result<Command, String> {
+    let Some(first) = args.first() else {
+        return Err("Not enough arguments have been provided.".to_string());
+    };
+
+    let rest = || args[1..].iter().cloned();
+    match first.as_str() {
+        "run"     => parse_run(rest()),
+        "check"   => Ok(Command::Check(parse_single("check", rest())?)),
+        "fmt"     => parse_fmt(rest()),
+        "debug"   => parse_debug(rest()),
+        "test"    => parse_test(rest()),
+        "repl"    => parse_none(Command::Repl, rest()),
+        "lsp"     => parse_lsp(rest()),
+        "license" => parse_none(Command::License, rest()),
+        "version" | "--version" | "-V" => parse_none(Command::Version, rest()),
+        "help"    | "--help"    | "-h" => parse_none(Command::Help, rest()),
+        //`oko <file>` is short for `oko run <file>`
+        _ => parse_run(args.iter().cloned()),
+    }
+}
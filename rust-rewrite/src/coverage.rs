@@ -0,0 +1,182 @@
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::rc::Rc;
+
+use crate::ast::{self, ElseBlock, Expr, StatSeq, Stmt, StmtKind, Visitor};
+use crate::executor::{catch, raise, Frame, Hooks, Scope};
+use crate::lexer::Span;
+
+
+//`oko run --coverage`, records which parts of a program ran. afterwards it prints a summary,
+//and writes the details in the lcov format that coverage tools and editors read.
+//every statement counts, as do both outcomes of every `if`, `elif` and `while` condition.
+
+//what a program consists of, spans are told apart by their offset
+#[derive(Default)]
+struct Program {
+    stmts: Vec<Span>,
+    conditions: Vec<Span>,
+    funs: Vec<(String, Span)>, //name and the whole `fun` statement
+}
+
+impl Visitor for Program {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.stmts.push(stmt.span);
+        match &stmt.kind {
+            StmtKind::Fun(decl) => self.funs.push((decl.name.clone(), stmt.span)),
+            StmtKind::While { condition, .. } => self.conditions.push(condition.span),
+            StmtKind::If(stat) => {
+                let mut next = Some(stat);
+                while let Some(stat) = next {
+                    self.conditions.push(stat.condition.span);
+                    next = match &stat.else_block {
+                        Some(ElseBlock::Elif(x)) => Some(x),
+                        _ => None,
+                    };
+                }
+            },
+            _ => {},
+        }
+        ast::walk_stmt(self, stmt);
+    }
+}
+
+
+#[derive(Default)]
+struct Coverage {
+    stmts: HashMap<usize, u64>,             //runs by offset
+    branches: HashMap<usize, (u64, u64)>,   //times taken and not taken, by offset of the condition
+    calls: HashMap<usize, u64>,             //by offset of the `fun` statement, functions may share a name
+}
+
+impl Hooks for Coverage {
+    fn stmt(&mut self, stmt: &Stmt, _: &mut Scope) {
+        *self.stmts.entry(stmt.span.offset).or_default() += 1;
+    }
+
+    fn branch(&mut self, condition: &Expr, taken: bool) {
+        let counts = self.branches.entry(condition.span.offset).or_default();
+        if taken { counts.0 += 1 } else { counts.1 += 1 }
+    }
+
+    fn entered(&mut self, frame: &Frame) {
+        *self.calls.entry(frame.declared.offset).or_default() += 1;
+    }
+}
+
+
+fn percent(hit: usize, found: usize) -> String {
+    if found == 0 { return "-".to_string() }
+    format!("{:.1}%", hit as f64 * 100.0 / found as f64)
+}
+
+//f.e. `3, 7-9`
+fn line_ranges(lines: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for &line in lines {
+        match ranges.last_mut() {
+            Some(last) if last.1 + 1 >= line => last.1 = line,
+            _ => ranges.push((line, line)),
+        }
+    }
+    let ranges: Vec<String> = ranges.iter()
+        .map(|&(a, b)| if a == b { a.to_string() } else { format!("{}-{}", a, b) })
+        .collect();
+    ranges.join(", ")
+}
+
+fn lcov(program: &Program, coverage: &Coverage, name: &str) -> String {
+    //tools match the data to files by their full path
+    let path = fs::canonicalize(name).map_or(name.to_string(), |x| x.display().to_string());
+    let mut out = format!("TN:\nSF:{}\n", path);
+
+    for (fun, span) in &program.funs {
+        out += &format!("FN:{},{}\n", span.line, fun);
+    }
+    for (fun, span) in &program.funs {
+        out += &format!("FNDA:{},{}\n", coverage.calls.get(&span.offset).unwrap_or(&0), fun);
+    }
+    let funs_hit = program.funs.iter().filter(|x| coverage.calls.contains_key(&x.1.offset)).count();
+    out += &format!("FNF:{}\nFNH:{}\n", program.funs.len(), funs_hit);
+
+    let mut branches_hit = 0;
+    for (block, span) in program.conditions.iter().enumerate() {
+        //`-` marks a condition that never ran at all
+        let counts = coverage.branches.get(&span.offset);
+        for (branch, count) in [counts.map(|x| x.0), counts.map(|x| x.1)].into_iter().enumerate() {
+            let taken = count.map_or("-".to_string(), |x| x.to_string());
+            if count.is_some_and(|x| x > 0) { branches_hit += 1; }
+            out += &format!("BRDA:{},{},{},{}\n", span.line, block, branch, taken);
+        }
+    }
+    out += &format!("BRF:{}\nBRH:{}\n", program.conditions.len() * 2, branches_hit);
+
+    //a line ran as often as the statement on it that ran most
+    let mut lines: BTreeMap<u32, u64> = BTreeMap::new();
+    for span in &program.stmts {
+        let runs = *coverage.stmts.get(&span.offset).unwrap_or(&0);
+        let line = lines.entry(span.line).or_default();
+        *line = runs.max(*line);
+    }
+    for (line, runs) in &lines {
+        out += &format!("DA:{},{}\n", line, runs);
+    }
+    out += &format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), lines.values().filter(|x| **x > 0).count());
+    out
+}
+
+fn summary(program: &Program, coverage: &Coverage, name: &str) -> String {
+    let stmts_hit = program.stmts.iter().filter(|x| coverage.stmts.contains_key(&x.offset)).count();
+    let branches_hit: usize = program.conditions.iter()
+        .map(|x| coverage.branches.get(&x.offset).map_or(0, |x| (x.0 > 0) as usize + (x.1 > 0) as usize))
+        .sum();
+    let funs_hit = program.funs.iter().filter(|x| coverage.calls.contains_key(&x.1.offset)).count();
+
+    let mut out = format!("Coverage of {}:\n", name);
+    let rows = [
+        ("statements", stmts_hit, program.stmts.len()),
+        ("branches", branches_hit, program.conditions.len() * 2),
+        ("functions", funs_hit, program.funs.len()),
+    ];
+    for (what, hit, found) in rows {
+        out += &format!("    {:10}  {:>6} of {:<6} {:>7}\n", what, hit, found, percent(hit, found));
+    }
+
+    let mut missed: Vec<u32> = program.stmts.iter().filter(|x| !coverage.stmts.contains_key(&x.offset)).map(|x| x.line).collect();
+    missed.sort();
+    missed.dedup();
+    if !missed.is_empty() {
+        out += &format!("Lines that never ran: {}\n", line_ranges(&missed));
+    }
+    out
+}
+
+
+//prints the summary to stderr and writes the lcov data to `lcov_path`
+fn report(program: &Program, coverage: &Coverage, name: &str, lcov_path: &str) -> Result<(), String> {
+    eprint!("{}", summary(program, coverage, name));
+    if fs::write(lcov_path, lcov(program, coverage, name)).is_err() {
+        return Err(format!("Unable to write the coverage data to '{}'.", lcov_path));
+    }
+    eprintln!("Coverage data was written to {}.", lcov_path);
+    Ok(())
+}
+
+//runs the program, then reports which parts of it ran.
+//a program that ends in a runtime error gets its coverage too, the error is raised again afterwards
+pub fn run(name: &str, root: &StatSeq, scope: &mut Scope, lcov_path: &str) -> Result<(), String> {
+    let mut program = Program::default();
+    program.visit_block(root);
+    program.conditions.sort_by_key(|x| x.offset); //the ones of an `elif` chain come before those inside of it
+
+    let coverage = Rc::new(RefCell::new(Coverage::default()));
+    scope.hooks = Some(coverage.clone());
+    let result = catch(|| root.eval(scope));
+    scope.hooks = None;
+
+    report(&program, &coverage.borrow(), name, lcov_path)?;
+    if let Err(x) = result { raise(x) }
+    Ok(())
+}
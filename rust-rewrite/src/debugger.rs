@@ -0,0 +1,216 @@
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+use crate::ast::{StatSeq, Stmt, StmtKind};
+use crate::executor::{catch, format_call_stack, Frame, Hooks, Obj, Scope};
+use crate::lexer;
+use crate::parser;
+
+
+//`oko debug`, a line debugger on the terminal. it pauses before the first line of the program,
+//and then wherever a breakpoint or a step says so. commands are read from stdin:
+const HELP: &str = "\
+Commands:
+    b, break <line>     pause whenever the line is reached
+    d, delete <line>    remove the breakpoint of a line
+    c, continue         run until the next breakpoint
+    s, step             run until the next line, entering function calls
+    n, next             run until the next line of this function, running calls in one go
+    o, out              run until the current function returns
+    v, vars             show the variables of the paused frame
+    bt, stack           show the call stack
+    l, list             show the code around the paused line
+    p, print <code>     run an expression or statement in the paused frame, f.e. `p x * 2` or `p x = 3`
+    q, quit             stop the program
+    h, help             show this help
+An empty line repeats the last command.";
+
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Run,
+    Step,
+    Over(usize), //pause once back at this call depth or above
+    Out(usize),  //pause once above this call depth
+}
+
+//a call depth and line, to tell when the program moved on to another line
+#[derive(PartialEq, Clone, Copy)]
+struct Position {
+    depth: usize,
+    line: u32,
+}
+
+struct Debugger {
+    source: Rc<str>,
+    breakpoints: BTreeSet<u32>,
+    mode: Mode,
+    position: Option<Position>,
+    line_start: *const Stmt, //the first statement run on the current line, reaching it again is a new iteration of a loop
+    last_command: String,
+}
+
+fn depth(scope: &Scope) -> usize {
+    scope.frame.as_ref().map_or(0, |x| x.depth)
+}
+
+impl Debugger {
+    //only the first statement on a line pauses, except when a loop comes back around to it
+    fn moved_on(&mut self, stmt: &Stmt, position: Position) -> bool {
+        if self.position == Some(position) && !std::ptr::eq(self.line_start, stmt) {
+            return false;
+        }
+        self.position = Some(position);
+        self.line_start = stmt;
+        true
+    }
+
+    fn should_pause(&self, position: Position) -> bool {
+        if self.breakpoints.contains(&position.line) { return true }
+        match self.mode {
+            Mode::Run => false,
+            Mode::Step => true,
+            Mode::Over(depth) => position.depth <= depth,
+            Mode::Out(depth) => position.depth < depth,
+        }
+    }
+
+    fn list(&self, line: u32) {
+        let first = line.saturating_sub(3).max(1);
+        for (index, text) in self.source.lines().enumerate().skip(first as usize - 1).take(7) {
+            let number = index as u32 + 1;
+            let marker = if number == line { ">" } else { " " };
+            let breakpoint = if self.breakpoints.contains(&number) { "*" } else { " " };
+            println!("{}{} {:4} | {}", marker, breakpoint, number, text);
+        }
+    }
+
+    fn breakpoint_line(&self, arg: &str) -> Option<u32> {
+        let line = arg.parse::<u32>().ok().filter(|x| *x > 0 && *x as usize <= self.source.lines().count());
+        if line.is_none() { println!("Expected a line number of the program, but got '{}'.", arg); }
+        line
+    }
+
+    //reads commands until one of them resumes the program
+    fn pause(&mut self, line: u32, scope: &mut Scope) {
+        let name = scope.frame.as_ref().map_or("<program>".to_string(), |x| x.name.clone());
+        println!("Paused at line {} in {}:", line, name);
+        println!("    {} | {}", line, self.source.lines().nth(line as usize - 1).unwrap_or("").trim());
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            _ = io::stdout().flush();
+            let mut input = String::new();
+            if stdin.lock().read_line(&mut input).unwrap_or(0) == 0 {
+                println!();
+                std::process::exit(0);
+            }
+
+            let mut input = input.trim().to_string();
+            if input.is_empty() { input = self.last_command.clone(); }
+            self.last_command = input.clone();
+            let (command, arg) = input.split_once(' ').unwrap_or((&input, ""));
+            let arg = arg.trim();
+
+            match command {
+                "b" | "break" => if let Some(x) = self.breakpoint_line(arg) {
+                    self.breakpoints.insert(x);
+                    println!("Breakpoint at line {}.", x);
+                },
+                "d" | "delete" => if let Some(x) = self.breakpoint_line(arg) {
+                    if !self.breakpoints.remove(&x) { println!("There is no breakpoint at line {}.", x); }
+                },
+                "c" | "continue" => { self.mode = Mode::Run; return },
+                "s" | "step" => { self.mode = Mode::Step; return },
+                "n" | "next" => { self.mode = Mode::Over(depth(scope)); return },
+                "o" | "out" => {
+                    if depth(scope) == 0 {
+                        println!("Not inside of a function, use `continue` to run the rest of the program.");
+                        continue;
+                    }
+                    self.mode = Mode::Out(depth(scope));
+                    return;
+                },
+                "v" | "vars" => {
+                    let mut vars: Vec<(&String, &Obj)> = scope.vars.iter().collect();
+                    vars.sort_by_key(|x| x.0);
+                    for (name, value) in vars {
+                        println!("{} = {:?}", name, value);
+                    }
+                },
+                "bt" | "stack" => {
+                    println!("At line {} in {}", line, name);
+                    println!("{}", format_call_stack(&scope.frame));
+                },
+                "l" | "list" => self.list(line),
+                "p" | "print" => eval_in(arg, scope),
+                "q" | "quit" => std::process::exit(0),
+                "h" | "help" => println!("{}", HELP),
+                x => println!("Unknown command '{}', `help` lists them all.", x),
+            }
+        }
+    }
+}
+
+impl Hooks for Debugger {
+    fn stmt(&mut self, stmt: &Stmt, scope: &mut Scope) {
+        let position = Position { depth: depth(scope), line: stmt.span.line };
+        if !self.moved_on(stmt, position) || !self.should_pause(position) { return }
+        self.pause(stmt.span.line, scope);
+    }
+
+    //the caller carries on in the middle of a line, which doesn't run through `stmt`.
+    //stepping pauses there, unless the step is still inside of the function that returned
+    fn returned(&mut self, frame: &Frame, value: &Obj, scope: &mut Scope) {
+        let position = Position { depth: depth(scope), line: frame.line };
+        let pause = match self.mode {
+            Mode::Run => false,
+            Mode::Step => true,
+            Mode::Over(depth) | Mode::Out(depth) => position.depth < depth,
+        };
+        if !pause { return }
+
+        self.position = Some(position);
+        self.line_start = std::ptr::null();
+        println!("{} returned {:?}.", frame.name, value);
+        self.pause(frame.line, scope);
+    }
+}
+
+//runs `print` input in the paused frame, a runtime error in it is only shown
+fn eval_in(code: &str, scope: &mut Scope) {
+    let source = if code.trim_end().ends_with([';', '}']) { code.to_string() } else { format!("{};", code) };
+    let mut stream = lexer::lex(&source);
+    let root = parser::parse_program(&mut stream);
+    if lexer::report_all(&source, &stream) > 0 { return }
+
+    //the debugger is busy, so whatever this runs doesn't pause
+    let hooks = scope.hooks.take();
+    let result = catch(|| {
+        for stmt in &root.stmts {
+            match &stmt.kind {
+                StmtKind::Expr(expr) => println!("{:?}", expr.eval(scope)),
+                _ => { stmt.eval(scope); },
+            }
+        }
+    });
+    scope.hooks = hooks;
+    if let Err(x) = result {
+        println!("Runtime Error: {}", x.msg);
+    }
+}
+
+
+pub fn run(source: Rc<str>, root: &StatSeq, scope: &mut Scope) {
+    println!("Debugging, `help` lists the commands.");
+    let debugger = Debugger {
+        source, breakpoints: BTreeSet::new(), mode: Mode::Step, position: None, line_start: std::ptr::null(), last_command: String::new(),
+    };
+    scope.hooks = Some(Rc::new(RefCell::new(debugger)));
+    root.eval(scope);
+    println!("The program finished.");
+}
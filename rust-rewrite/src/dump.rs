@@ -0,0 +1,191 @@
+
+use crate::ast::{ElseBlock, Expr, ExprKind, FunctionCall, IfStat, Param, StatSeq, Stmt, StmtKind};
+use crate::json::Json;
+use crate::lexer::{Span, Stream};
+
+
+//debugging output for `--dump-tokens` and `--dump-ast`.
+//the tree is always converted to json first, the indented form is rendered from that,
+//so both dumps are guaranteed to show the same thing.
+
+
+//one token per line, f.e.:
+//  2:3     Identifier(a)
+pub fn tokens(stream: &Stream) -> String {
+    let mut out = String::new();
+    for token in stream.tokens() {
+        let pos = format!("{}:{}", token.span.line, token.span.col);
+        out.push_str(&format!("{:<8}{}\n", pos, token.data));
+    }
+    out
+}
+
+
+fn span(span: Span) -> Json {
+    Json::object(vec![
+        ("offset", Json::Number(span.offset as f64)),
+        ("len",    Json::Number(span.len as f64)),
+        ("line",   Json::Number(span.line as f64)),
+        ("col",    Json::Number(span.col as f64)),
+    ])
+}
+
+fn node(kind: &str, at: Span, mut fields: Vec<(&str, Json)>) -> Json {
+    fields.insert(0, ("kind", Json::str(kind)));
+    fields.push(("span", span(at)));
+    Json::object(fields)
+}
+
+pub fn block(block: &StatSeq) -> Json {
+    node("Block", block.span, vec![
+        ("stmts", Json::Array(block.stmts.iter().map(stmt).collect())),
+    ])
+}
+
+fn if_stat(stat: &IfStat, at: Span) -> Json {
+    let else_block = match &stat.else_block {
+        Some(ElseBlock::Elif(x)) => if_stat(x, x.condition.span),
+        Some(ElseBlock::Else(x)) => block(x),
+        None => Json::Null,
+    };
+    node("If", at, vec![
+        ("condition", expr(&stat.condition)),
+        ("if_block", block(&stat.if_block)),
+        ("else_block", else_block),
+    ])
+}
+
+fn param(param: &Param) -> Json {
+    node("Param", param.span, vec![
+        ("name", Json::str(&param.name)),
+        ("default", param.default.as_ref().map_or(Json::Null, expr)),
+    ])
+}
+
+fn stmt(stmt: &Stmt) -> Json {
+    let at = stmt.span;
+    match &stmt.kind {
+        StmtKind::Import { mod_name } => node("Import", at, vec![("mod_name", Json::str(mod_name))]),
+        StmtKind::Assign { var_name, op, expr: value } => node("Assign", at, vec![
+            ("var_name", Json::str(var_name)),
+            ("op", Json::String(op.to_string())),
+            ("expr", expr(value)),
+        ]),
+        StmtKind::Return { expr: value, tail_call } => node("Return", at, vec![
+            ("expr", value.as_ref().map_or(Json::Null, expr)),
+            ("tail_call", Json::Bool(*tail_call)),
+        ]),
+        StmtKind::Fun(decl) => node("Fun", at, vec![
+            ("name", Json::str(&decl.name)),
+            ("args", Json::Array(decl.args.iter().map(param).collect())),
+            ("rest", decl.rest.as_ref().map_or(Json::Null, param)),
+            ("body", block(&decl.body)),
+        ]),
+        StmtKind::Expr(x) => node("Expr", at, vec![("expr", expr(x))]),
+        StmtKind::If(x) => if_stat(x, at),
+        StmtKind::While { condition, body } => node("While", at, vec![
+            ("condition", expr(condition)),
+            ("body", block(body)),
+        ]),
+        StmtKind::For { elem_name, array, body, .. } => node("For", at, vec![
+            ("elem_name", Json::str(elem_name)),
+            ("array", expr(array)),
+            ("body", block(body)),
+        ]),
+        StmtKind::Try { body, catch, finally } => node("Try", at, vec![
+            ("body", block(body)),
+            ("catch", catch.as_ref().map_or(Json::Null, |x| node("Catch", x.span, vec![
+                ("name", Json::str(&x.name)),
+                ("body", block(&x.body)),
+            ]))),
+            ("finally", finally.as_ref().map_or(Json::Null, block)),
+        ]),
+        StmtKind::Error => node("Error", at, vec![]),
+    }
+}
+
+fn call_args(call: &FunctionCall) -> Json {
+    Json::Array(call.args.iter().map(expr).collect())
+}
+
+fn expr(expr_: &Expr) -> Json {
+    let at = expr_.span;
+    match &expr_.kind {
+        ExprKind::Binary { op, left, right } => node("Binary", at, vec![
+            ("op", Json::str(op)),
+            ("left", expr(left)),
+            ("right", expr(right)),
+        ]),
+        ExprKind::Unary { op, operand } => node("Unary", at, vec![
+            ("op", Json::str(op)),
+            ("operand", expr(operand)),
+        ]),
+        ExprKind::Propagate(x) => node("Propagate", at, vec![("expr", expr(x))]),
+        ExprKind::Int { value, .. }   => node("Int", at, vec![("value", Json::Number(*value as f64))]),
+        ExprKind::Float { value, .. } => node("Float", at, vec![("value", Json::Number(*value))]),
+        ExprKind::Str(x)      => node("Str", at, vec![("value", Json::str(x))]),
+        ExprKind::Format(x)   => node("Format", at, vec![("parts", Json::Array(x.iter().map(expr).collect()))]),
+        ExprKind::Variable(x) => node("Variable", at, vec![("name", Json::str(x))]),
+        ExprKind::Call(call)  => node("Call", at, vec![
+            ("name", Json::str(&call.name)),
+            ("args", call_args(call)),
+        ]),
+        ExprKind::ModAccess { mod_name, member } => node("ModAccess", at, vec![
+            ("mod_name", Json::str(mod_name)),
+            ("name", Json::str(&member.name)),
+            ("args", call_args(member)),
+        ]),
+        ExprKind::Array(x) => node("Array", at, vec![("elems", Json::Array(x.iter().map(expr).collect()))]),
+        ExprKind::Error    => node("Error", at, vec![]),
+    }
+}
+
+
+//renders the json form of the tree as an indented outline, f.e.:
+//  Assign @1:1 var_name="a" op=":="
+//    expr: Int @1:6 value=5
+pub fn tree(json: &Json) -> String {
+    let mut out = String::new();
+    tree_node(json, 0, &mut out);
+    out
+}
+
+fn tree_node(json: &Json, depth: usize, out: &mut String) {
+    let Json::Object(fields) = json else {
+        //plain values, which only show up as elements of a list
+        out.push_str(&format!("{}\n", json));
+        return;
+    };
+
+    let kind = json.get("kind").and_then(|x| if let Json::String(x) = x { Some(x.as_str()) } else { None });
+    out.push_str(kind.unwrap_or("?"));
+    if let (Some(line), Some(col)) = (json.get("span").and_then(|x| x.get("line")), json.get("span").and_then(|x| x.get("col"))) {
+        out.push_str(&format!(" @{}:{}", line, col));
+    }
+    for (key, value) in fields {
+        if key == "kind" || key == "span" || !value.is_scalar() { continue }
+        out.push_str(&format!(" {}={}", key, value));
+    }
+    out.push('\n');
+
+    let indent = "  ".repeat(depth + 1);
+    for (key, value) in fields {
+        match value {
+            Json::Object(_) if key != "span" => {
+                out.push_str(&format!("{}{}: ", indent, key));
+                tree_node(value, depth + 1, out);
+            },
+            Json::Array(elems) if elems.iter().all(Json::is_scalar) => {
+                out.push_str(&format!("{}{}: {}\n", indent, key, value));
+            },
+            Json::Array(elems) => {
+                out.push_str(&format!("{}{}:\n", indent, key));
+                for elem in elems {
+                    out.push_str(&format!("{}  ", indent));
+                    tree_node(elem, depth + 2, out);
+                }
+            },
+            _ => {},
+        }
+    }
+}
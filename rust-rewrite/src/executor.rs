@@ -1,65 +1,382 @@
 
 
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::rc::Rc;
-use crate::{lexer, parser};
+use crate::ast;
+use crate::builtins;
+use crate::lexer::Span;
 
 
 #[derive(Clone)]
 pub struct Fun {
-    args: Vec<String>,
-    body: Rc<parser::StatSeq>,
+    decl: Rc<ast::FunctionDeclare>,
+    span: Span, //the whole `fun` statement
+    propagates: bool, //whether it uses `?`, calls of those have to stop it
 }
 
+//finds a `?` in a function, not counting the functions declared inside of it
+struct FindPropagate {
+    found: bool,
+}
+
+impl ast::Visitor for FindPropagate {
+    fn visit_stmt(&mut self, stmt: &ast::Stmt) {
+        if let ast::StmtKind::Fun(_) = stmt.kind { return }
+        ast::walk_stmt(self, stmt);
+    }
+
+    fn visit_expr(&mut self, expr: &ast::Expr) {
+        if let ast::ExprKind::Propagate(_) = expr.kind { self.found = true }
+        ast::walk_expr(self, expr);
+    }
+}
+
+impl Fun {
+    fn new(decl: Rc<ast::FunctionDeclare>, span: Span) -> Self {
+        let mut find = FindPropagate { found: false };
+        for default in decl.args.iter().filter_map(|x| x.default.as_ref()) {
+            ast::Visitor::visit_expr(&mut find, default);
+        }
+        ast::Visitor::visit_block(&mut find, &decl.body);
+        Fun { decl, span, propagates: find.found }
+    }
+}
+
+
+//how deeply oko functions may call each other, unless `--max-call-depth` says otherwise
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+thread_local! {
+    //where the stack of the thread starts, and how much of it calls may use
+    static STACK: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+//lets calls check how much stack is left, `base` is the address of a local near the start of the thread.
+//how much stack a call takes depends on how deeply its body nests, so a higher `--max-call-depth`
+//alone could still overflow the stack, and crash instead of giving a runtime error
+pub fn set_stack(base: usize, usable: usize) {
+    STACK.set(Some((base, usable)));
+}
+
+fn out_of_stack() -> bool {
+    let here = 0u8;
+    let here = std::hint::black_box(&here) as *const u8 as usize;
+    STACK.get().is_some_and(|(base, usable)| base.abs_diff(here) > usable)
+}
+
+//one function call in progress, frames link back to their caller
+pub struct Frame {
+    pub name: String,
+    pub declared: Span, //the `fun` statement of the function, which tells apart functions of the same name
+    pub line: u32, //where the function was called from
+    pub depth: usize,
+    pub parent: Option<Rc<Frame>>,
+}
+
+//lets tools watch the program as it runs, f.e. the debugger of `oko debug`.
+//while a hook runs, `scope.hooks` is still set, so code it evaluates has to take them out first
+pub trait Hooks {
+    //called before every statement
+    fn stmt(&mut self, _stmt: &ast::Stmt, _scope: &mut Scope) {}
+    //called once the condition of an `if`, `elif` or `while` decided where to go
+    fn branch(&mut self, _condition: &ast::Expr, _taken: bool) {}
+    //called when a function is called, before its arguments are bound
+    fn entered(&mut self, _frame: &Frame) {}
+    //called once a function returned `value`, `scope` is the one of the caller.
+    //a tail call doesn't return in between, it is part of the same call,
+    //and a call that an error unwinds out of doesn't return at all
+    fn returned(&mut self, _frame: &Frame, _value: &Obj, _scope: &mut Scope) {}
+}
 
 #[derive(Clone)]
 pub struct Scope {
     pub vars: HashMap<String, Obj>,
     pub funs: HashMap<String, Fun>,
-    pub ret_val: Obj,
-    pub ret_flag: bool,
+    pub args: Rc<Vec<String>>, //what `prog::args` returns
+    pub frame: Option<Rc<Frame>>, //`None` at the top level of the program
+    pub max_call_depth: usize,
+    pub hooks: Option<Rc<RefCell<dyn Hooks>>>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub enum Obj {
-    Invalid,
     Int(i64),
     Float(f64),
     String(String),
     Array(Vec<Obj>),
     Bool(bool),
+    Error(Rc<RuntimeError>), //a caught error, or one a builtin returned
     Nil,
 }
 
-impl std::fmt::Display for Obj {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Obj::Invalid    => error("Cannot render invalid object.".to_string()),
-            Obj::Nil        => write!(f, "Nil"),
-            Obj::Int(x)     => write!(f, "{}", x),
-            Obj::Float(x)   => write!(f, "{}", x),
-            Obj::String(x)  => write!(f, "{}", x),
-            Obj::Bool(x)    => write!(f, "{}", x),
-            Obj::Array(x)   => {
-                write!(f, "[")?;
-                for elem in x {
-                    elem.fmt(f)?;
+//there are two ways to render a value:
+// - the canonical one, `Display`, is what `io::println`, `tu::toString`, interpolation and the repl show.
+//   strings are printed as they are, but quoted inside of arrays, f.e. `[1, 2.0, "a"]`
+// - the debug one, `Debug`, quotes and escapes every string, so `"a"` and `a` can be told apart.
+//   `{:#?}` additionally puts the elements of nested arrays on their own lines
+#[derive(Clone, Copy)]
+struct Render {
+    quote: bool,
+    pretty: bool,
+}
+
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for char in value.chars() {
+        match char {
+            '"'    => write!(f, "\\\"")?,
+            '\\'   => write!(f, "\\\\")?,
+            '\n'   => write!(f, "\\n")?,
+            '\t'   => write!(f, "\\t")?,
+            '\x1B' => write!(f, "\\e")?,
+            x      => write!(f, "{}", x)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+//`open` are the arrays currently being rendered. a value can't contain itself yet,
+//but once arrays are shared by reference this keeps printing one that does from looping forever
+fn write_obj(f: &mut std::fmt::Formatter<'_>, obj: &Obj, how: Render, depth: usize, open: &mut Vec<*const Vec<Obj>>) -> std::fmt::Result {
+    match obj {
+        Obj::Nil        => write!(f, "Nil"),
+        Obj::Int(x)     => write!(f, "{}", x),
+        Obj::Float(x)   => write!(f, "{:?}", x), //always with a fraction, f.e. `1.0`
+        Obj::String(x) if how.quote => write_escaped(f, x),
+        Obj::String(x)  => write!(f, "{}", x),
+        Obj::Bool(x)    => write!(f, "{}", x),
+        Obj::Error(x) if how.quote => {
+            write!(f, "Error({}, ", x.code)?;
+            write_escaped(f, &x.msg)?;
+            write!(f, ", line {}", x.line)?;
+            if let Some(cause) = &x.cause {
+                write!(f, ", cause: ")?;
+                write_obj(f, &Obj::Error(cause.clone()), how, depth, open)?;
+            }
+            write!(f, ")")
+        },
+        Obj::Error(x)   => write!(f, "{}", x.msg),
+        Obj::Array(x) if open.contains(&(x as *const Vec<Obj>)) => write!(f, "[...]"),
+        Obj::Array(x) if x.is_empty() => write!(f, "[]"),
+        Obj::Array(x)   => {
+            open.push(x);
+            let inner = Render { quote: true, ..how };
+            let nested = how.pretty && x.iter().any(|x| matches!(x, Obj::Array(_)));
+
+            write!(f, "[")?;
+            for (i, elem) in x.iter().enumerate() {
+                if nested {
+                    write!(f, "{}\n{}", if i > 0 { "," } else { "" }, "    ".repeat(depth + 1))?;
+                } else if i > 0 {
                     write!(f, ", ")?;
                 }
-                write!(f, "]")?;
-                Ok(())
+                write_obj(f, elem, inner, depth + 1, open)?;
             }
+            if nested { write!(f, "\n{}", "    ".repeat(depth))?; }
+            write!(f, "]")?;
+
+            open.pop();
+            Ok(())
         }
     }
 }
 
+impl std::fmt::Display for Obj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_obj(f, self, Render { quote: false, pretty: false }, 0, &mut vec![])
+    }
+}
 
-fn error(msg: String) -> ! {
-    eprintln!("Runtime Error: {}", msg);
-    std::process::exit(1);
+impl std::fmt::Debug for Obj {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_obj(f, self, Render { quote: true, pretty: f.alternate() }, 0, &mut vec![])
+    }
 }
 
 
+//the codes of runtime errors, a `catch` can tell errors apart by them
+pub const ERR_TYPE:   &str = "type";             //f.e. `1 + "a"`
+pub const ERR_NAME:   &str = "name";             //an undefined variable, function or module member, or a variable defined twice
+pub const ERR_ARGS:   &str = "arguments";        //a call with the wrong number of arguments
+pub const ERR_ZERO:   &str = "division-by-zero"; //of integers, floats give `inf` or `NaN` instead
+pub const ERR_RANGE:  &str = "out-of-range";     //an integer result that doesn't fit into 64 bits, or isn't an integer at all
+pub const ERR_DEPTH:  &str = "call-depth";       //see `DEFAULT_MAX_CALL_DEPTH`
+pub const ERR_SYNTAX: &str = "syntax";
+pub const ERR_SYSTEM: &str = "system";           //the operating system refused something
+pub const ERR_ASSERT: &str = "assertion";        //the `assert` module
+pub const ERR_THROWN: &str = "thrown";           //`prog::throw` without a code of its own
+//the ones builtins return as values instead of raising them
+pub const ERR_NOT_FOUND:      &str = "not-found";         //f.e. a file that doesn't exist
+pub const ERR_PERMISSION:     &str = "permission-denied";
+pub const ERR_INVALID_DATA:   &str = "invalid-data";      //f.e. a file that isn't valid text
+pub const ERR_INVALID_NUMBER: &str = "invalid-number";    //a string `tu::toNumber` can't read a number from
+
+//a runtime error. it either unwinds up to whoever handles it (see `catch`),
+//or is a value, f.e. in a `catch` block or returned by a builtin that failed
+#[derive(Clone)]
+pub struct RuntimeError {
+    pub msg: String,
+    pub code: String,
+    pub line: u32, //0 if it didn't happen in the program, f.e. in a call by `oko test`
+    pub frame: Option<Rc<Frame>>, //the call it happened in
+    pub cause: Option<Rc<RuntimeError>>, //the error that led to this one, see `tu::makeError`
+}
+
+//equal errors are the same error, no matter in which call it happened
+impl PartialEq for RuntimeError {
+    fn eq(&self, other: &Self) -> bool {
+        self.msg == other.msg && self.code == other.code && self.line == other.line && self.cause == other.cause
+    }
+}
+
+//where the program currently is. statements update the line and calls the frame,
+//so an error can tell where it happened without being handed the scope
+#[derive(Clone, Default)]
+struct Location {
+    line: u32,
+    frame: Option<Rc<Frame>>,
+}
+
+//why the program unwinds
+enum Unwind {
+    Error(RuntimeError),
+    Propagate(Rc<RuntimeError>), //a `?` returning an error value from the function it is in
+}
+
+//what actually unwinds, the reason isn't `Send` and waits in `UNWINDING` meanwhile
+pub struct Unwinding;
+
+thread_local! {
+    static LOCATION: RefCell<Location> = RefCell::new(Location::default());
+    static UNWINDING: RefCell<Option<Unwind>> = const { RefCell::new(None) };
+}
+
+//a new error that happened where the program currently is
+pub fn new_error(code: &str, msg: String) -> RuntimeError {
+    let Location { line, frame } = LOCATION.with_borrow(|x| x.clone());
+    RuntimeError { msg, code: code.to_string(), line, frame, cause: None }
+}
+
+//unwinds out of the running program, `main` reports the error if nothing catches it before
+pub fn error(code: &str, msg: String) -> ! {
+    raise(new_error(code, msg));
+}
+
+//unwinds with an error as it is, f.e. one that was caught before
+pub fn raise(error: RuntimeError) -> ! {
+    unwind(Unwind::Error(error));
+}
+
+fn unwind(reason: Unwind) -> ! {
+    UNWINDING.set(Some(reason));
+    std::panic::panic_any(Unwinding);
+}
+
+//runs `f`, stopping at whatever unwinds out of it.
+//other panics are bugs of the interpreter itself, those keep going
+fn stop_unwinding<T>(f: impl FnOnce() -> T) -> Result<T, Unwind> {
+    //the calls that were unwound out of didn't get to restore the location
+    let location = LOCATION.with_borrow(|x| x.clone());
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(x) => Ok(x),
+        Err(payload) => {
+            LOCATION.set(location);
+            if !payload.is::<Unwinding>() { std::panic::resume_unwind(payload) }
+            Err(UNWINDING.take().expect("The program unwound without a reason."))
+        },
+    }
+}
+
+//runs `f`, stopping at the first runtime error in it
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, RuntimeError> {
+    match stop_unwinding(f) {
+        Ok(x) => Ok(x),
+        Err(Unwind::Error(x)) => Err(x),
+        //a `?` of the function `f` runs in, f.e. in the debugger, has no call to return from
+        Err(Unwind::Propagate(x)) => Err((*x).clone()),
+    }
+}
+
+//f.e.:
+//  Runtime Error at line 2: Unable to perform division of divergent types.
+//  Call stack (innermost first):
+//      at divide (line 5)
+//      at <program>
+pub fn format_error(error: &RuntimeError) -> String {
+    let mut out = match error.line {
+        0 => format!("Runtime Error: {}", error.msg),
+        x => format!("Runtime Error at line {}: {}", x, error.msg),
+    };
+    if error.frame.is_some() {
+        out += &format!("\n{}", format_call_stack(&error.frame));
+    }
+    let mut cause = &error.cause;
+    while let Some(x) = cause {
+        out += &format!("\nCaused by: {}", x.msg);
+        cause = &x.cause;
+    }
+    out
+}
+
+
+//line 0 is a call that didn't come from oko code, f.e. one by `oko test`
+fn format_frame(frame: &Frame) -> String {
+    if frame.line == 0 { return format!("\n    at {}", frame.name) }
+    format!("\n    at {} (line {})", frame.name, frame.line)
+}
+
+//the innermost call first, long runs of the same recursive call are collapsed, f.e.:
+//      at fac (line 5)
+//      ... 9997 more calls to fac
+//      at fac (line 9)
+pub fn format_call_stack(frame: &Option<Rc<Frame>>) -> String {
+    let mut frames = vec![];
+    let mut next = frame.as_deref();
+    while let Some(x) = next {
+        frames.push(x);
+        next = x.parent.as_deref();
+    }
+
+    let mut out = String::from("Call stack (innermost first):");
+    let mut i = 0;
+    while i < frames.len() {
+        let mut run = 1;
+        while i + run < frames.len() && frames[i + run].name == frames[i].name && frames[i + run].line == frames[i].line {
+            run += 1;
+        }
+        out += &format_frame(frames[i]);
+        if run > 2 {
+            out += &format!("\n    ... {} more calls to {}", run - 2, frames[i].name);
+        }
+        if run > 1 {
+            out += &format_frame(frames[i]);
+        }
+        i += run;
+    }
+    out += "\n    at <program>";
+    out
+}
+
+
+//an integer result that is `None` because it doesn't fit into 64 bits
+fn checked(result: Option<i64>, what: &str) -> Obj {
+    let Some(x) = result else {
+        error(ERR_RANGE, format!("The result of the {} doesn't fit into an integer.", what));
+    };
+    Obj::Int(x)
+}
+
+//`x ^ y` of integers, only `x` of 0, 1 and -1 can take exponents that don't even fit into a u32
+fn int_pow(x: i64, y: i64) -> Option<i64> {
+    match (x, u32::try_from(y)) {
+        (_, Ok(y))  => x.checked_pow(y),
+        (0 | 1, _)  => Some(x),
+        (-1, _)     => Some(if y % 2 == 0 { 1 } else { -1 }),
+        _           => None,
+    }
+}
+
 //this is formulated as a function for performance reasons
 fn apply_binary_op(lhs: &Obj, rhs: &Obj, op: &str) -> Obj {
     if let Obj::Nil = lhs { return Obj::Nil }
@@ -67,75 +384,79 @@ fn apply_binary_op(lhs: &Obj, rhs: &Obj, op: &str) -> Obj {
 
     match op {
         "+" => match (lhs, rhs) {
-            (Obj::Int(x),    Obj::Int(y)   ) => Obj::Int   (x         + y),
+            (Obj::Int(x),    Obj::Int(y)   ) => checked(x.checked_add(*y), "addition"),
             (Obj::String(x), Obj::String(y)) => Obj::String(x.clone() + y),
             (Obj::Float(x),  Obj::Float(y) ) => Obj::Float (x         + y),
-            _ => error("Unable to perform addition of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform addition of divergent types.".to_string()),
         },
         "-" => match (lhs, rhs) {
-            (Obj::Int(x),   Obj::Int(y)  ) => Obj::Int  (x - y),
+            (Obj::Int(x),   Obj::Int(y)  ) => checked(x.checked_sub(*y), "subtraction"),
             (Obj::Float(x), Obj::Float(y)) => Obj::Float(x - y),
-            _ => error("Unable to perform subtraction of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform subtraction of divergent types.".to_string()),
         },
         "*" => match (lhs, rhs) {
-            (Obj::Int(x),   Obj::Int(y)  ) => Obj::Int  (x * y),
+            (Obj::Int(x),   Obj::Int(y)  ) => checked(x.checked_mul(*y), "multiplication"),
             (Obj::Float(x), Obj::Float(y)) => Obj::Float(x * y),
-            _ => error("Unable to perform multiplication of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform multiplication of divergent types.".to_string()),
         },
         "/" => match (lhs, rhs) {
-            (Obj::Int(x),   Obj::Int(y)  ) => Obj::Int  (x / y),
+            (Obj::Int(_),   Obj::Int(0)  ) => error(ERR_ZERO, "Unable to divide an integer by zero.".to_string()),
+            (Obj::Int(x),   Obj::Int(y)  ) => checked(x.checked_div(*y), "division"),
             (Obj::Float(x), Obj::Float(y)) => Obj::Float(x / y),
-            _ => error("Unable to perform division of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform division of divergent types.".to_string()),
         },
         "^" => match (lhs, rhs) {
-            (Obj::Int(x),   Obj::Int(y)  ) => Obj::Int  (x.pow(*y as u32)),
+            (Obj::Int(_),   Obj::Int(y)  ) if *y < 0 =>
+                error(ERR_RANGE, "Unable to raise an integer to a negative power, the result isn't an integer.".to_string()),
+            (Obj::Int(x),   Obj::Int(y)  ) => checked(int_pow(*x, *y), "exponentiation"),
             (Obj::Float(x), Obj::Float(y)) => Obj::Float(x.powf(*y)),
-            _ => error("Unable to perform exponentiation of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform exponentiation of divergent types.".to_string()),
         },
         "%" => match (lhs, rhs) {
-            (Obj::Int(x),   Obj::Int(y)  ) => Obj::Int  (x % y),
+            (Obj::Int(_),   Obj::Int(0)  ) => error(ERR_ZERO, "Unable to perform modulo of an integer by zero.".to_string()),
+            (Obj::Int(x),   Obj::Int(y)  ) => checked(x.checked_rem(*y), "modulo"),
             (Obj::Float(x), Obj::Float(y)) => Obj::Float(x % y),
-            _ => error("Unable to perform modulo of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform modulo of divergent types.".to_string()),
         },
         ">" => match (lhs, rhs) {
             (Obj::Int(x),   Obj::Int(y)  ) => Obj::Bool(x > y),
             (Obj::Float(x), Obj::Float(y)) => Obj::Bool(x > y),
-            _ => error("Unable to perform comparision of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform comparision of divergent types.".to_string()),
         },
         "<" => match (lhs, rhs) {
             (Obj::Int(x),   Obj::Int(y)  ) => Obj::Bool(x < y),
             (Obj::Float(x), Obj::Float(y)) => Obj::Bool(x < y),
-            _ => error("Unable to perform comparision of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform comparision of divergent types.".to_string()),
         },
         ">=" => match (lhs, rhs) {
             (Obj::Int(x),   Obj::Int(y)  ) => Obj::Bool(x >= y),
             (Obj::Float(x), Obj::Float(y)) => Obj::Bool(x >= y),
-            _ => error("Unable to perform comparision of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform comparision of divergent types.".to_string()),
         },
         "<=" => match (lhs, rhs) {
             (Obj::Int(x),   Obj::Int(y)  ) => Obj::Bool(x <= y),
             (Obj::Float(x), Obj::Float(y)) => Obj::Bool(x <= y),
-            _ => error("Unable to perform comparision of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform comparision of divergent types.".to_string()),
         },
         "==" => match (lhs, rhs) {
             (Obj::Int(x),   Obj::Int(y)  ) => Obj::Bool(x == y),
             (Obj::Float(x), Obj::Float(y)) => Obj::Bool(x == y),
-            _ => error("Unable to perform comparision of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform comparision of divergent types.".to_string()),
         },
         "!=" => match (lhs, rhs) {
             (Obj::Int(x),   Obj::Int(y)  ) => Obj::Bool(x != y),
             (Obj::Float(x), Obj::Float(y)) => Obj::Bool(x != y),
-            _ => error("Unable to perform comparision of divergent types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform comparision of divergent types.".to_string()),
         },
         "&&" => match (lhs, rhs) {
             (Obj::Bool(x), Obj::Bool(y)) => Obj::Bool(*x && *y),
-            _ => error("Unable to perform boolean and of non-boolean types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform boolean and of non-boolean types.".to_string()),
         },
         "||" => match (lhs, rhs) {
             (Obj::Bool(x), Obj::Bool(y)) => Obj::Bool(*x || *y),
-            _ => error("Unable to perform boolean or of non-boolean types.".to_string()),
+            _ => error(ERR_TYPE, "Unable to perform boolean or of non-boolean types.".to_string()),
         },
-        _ => Obj::Invalid,
+        _ => error(ERR_SYNTAX, format!("Unknown binary operator {}.", op)),
     }
 
 }
@@ -144,256 +465,525 @@ fn apply_binary_op(lhs: &Obj, rhs: &Obj, op: &str) -> Obj {
 //these should all makes sense.
 //rust doesn't have truthy values, because it's an actually programming language.
 //(unlike *cough* JS *cough*, jk ofc :3)
-fn truthiness(obj: Obj) -> bool {
+pub fn truthiness(obj: Obj) -> bool {
     match obj {
         Obj::Bool(x)       => x,
         Obj::Nil           => false,
-        Obj::Invalid       => false,
-        Obj::String(ref x) => x.len() > 0,
+        Obj::String(ref x) => !x.is_empty(),
         Obj::Int(x)        => x != 0,
         Obj::Float(x)      => x != 0.0,
-        Obj::Array(x)      => x.len() > 0,
+        Obj::Array(x)      => !x.is_empty(),
+        Obj::Error(_)      => true,
     }
 }
 
 
 
 
-impl parser::Nodeable for parser::StatSeq {
-    fn eval(&self, scope: &mut Scope) -> Obj {
-        for node in &self.nodes {
-            node.eval(scope);
+//how a statement finished. statements don't produce values,
+//this only tells the enclosing blocks and loops whether to carry on
+pub enum Flow {
+    Next,
+    Return(Obj),
+    TailCall(Vec<Obj>), //arguments of a `return f(...)` inside of `f`, see `tailcall.rs`
+}
 
-            if scope.ret_flag { break; }
+impl ast::StatSeq {
+    pub fn eval(&self, scope: &mut Scope) -> Flow {
+        for stmt in &self.stmts {
+            match stmt.eval(scope) {
+                Flow::Next => {},
+                x => return x,
+            }
         }
 
-        Obj::Invalid
+        Flow::Next
     }
 }
 
-impl parser::Nodeable for parser::ImportStat {
-    fn eval(&self, _scope: &mut Scope) -> Obj {
-        let _ = self.mod_name;
-        //built-in's are compiled into execute,
-        //so "importing" doesn't have semantic meaning.
-        //there is not to "load" into memory.
-        Obj::Invalid 
-    }
-}
 
-impl parser::Nodeable for parser::VariableAssign {
-    fn eval(&self, scope: &mut Scope) -> Obj {
-        match self.op {
-            lexer::TokenClass::Define => {
-                if scope.vars.contains_key(&self.var_name) {
-                    error(format!("Variable of name {} is already defined in scope.", self.var_name));
-                }
-                let expr = self.expr.eval(scope);
-                scope.vars.insert(self.var_name.clone(), expr);
-            },
-            lexer::TokenClass::Assign => {
-                if let None = scope.vars.remove(&self.var_name) {
-                    error(format!("Variable of name {} is not defined in scope.", self.var_name)); 
-                }
-                let expr = self.expr.eval(scope);
-                scope.vars.insert(self.var_name.clone(), expr);
+fn eval_assign(scope: &mut Scope, var_name: &String, op: ast::AssignOp, expr: &ast::Expr) {
+    match op {
+        ast::AssignOp::Define => {
+            if scope.vars.contains_key(var_name) {
+                error(ERR_NAME, format!("Variable of name {} is already defined in scope.", var_name));
             }
-            lexer::TokenClass::AssignOp(ref x) => {
-                let expr = &self.expr.eval(scope);
-                let Some(ref var) = scope.vars.remove(&self.var_name) else { 
-                    error(format!("Variable of name {} is not defined in scope.", self.var_name)); 
-                };
-                let new = match x.as_str() {
-                    "+=" => apply_binary_op(var, expr, "+"),
-                    "-=" => apply_binary_op(var, expr, "-"),
-                    "*=" => apply_binary_op(var, expr, "*"),
-                    "/=" => apply_binary_op(var, expr, "/"),
-                    _ => unreachable!(),
-                };
-                scope.vars.insert(self.var_name.clone(), new);
-            },
-            _ => unreachable!(),
+            let expr = expr.eval(scope);
+            scope.vars.insert(var_name.clone(), expr);
+        },
+        //the variable stays as it was until the new value is there, f.e. if a `try` catches an error of it
+        ast::AssignOp::Assign => {
+            if !scope.vars.contains_key(var_name) {
+                error(ERR_NAME, format!("Variable of name {} is not defined in scope.", var_name));
+            }
+            let expr = expr.eval(scope);
+            scope.vars.insert(var_name.clone(), expr);
         }
-
-        Obj::Invalid
+        ast::AssignOp::Add | ast::AssignOp::Sub | ast::AssignOp::Mul | ast::AssignOp::Div => {
+            let expr = &expr.eval(scope);
+            let Some(var) = scope.vars.get(var_name) else {
+                error(ERR_NAME, format!("Variable of name {} is not defined in scope.", var_name));
+            };
+            let new = apply_binary_op(var, expr, op.binary_op().unwrap());
+            scope.vars.insert(var_name.clone(), new);
+        },
     }
 }
 
-impl parser::Nodeable for parser::BinaryExpr {
-    fn eval(&self, scope: &mut Scope) -> Obj {
-        let left  = self.left.eval(scope);
-        let right = self.right.eval(scope);
-
-        apply_binary_op(&left, &right, self.op.as_str())
+//whether a condition holds, and tells the hooks about it
+fn eval_condition(scope: &mut Scope, condition: &ast::Expr) -> bool {
+    let taken = truthiness(condition.eval(scope));
+    if let Some(hooks) = &scope.hooks {
+        hooks.borrow_mut().branch(condition, taken);
     }
+    taken
 }
 
-impl parser::Nodeable for parser::UnaryExpr {
-    fn eval(&self, scope: &mut Scope) -> self::Obj {
-        let expr = self.operand.eval(scope);
-        match (self.op.as_str(), expr) {
-            ("!", Obj::Bool(x))   => Obj::Bool(!x),
-            ("-", Obj::Int(x))    => Obj::Int(-x),
-            ("-", Obj::Float(x))  => Obj::Float(-x),
-            _ => error(format!("Unable to perform unary operator {} on given type.", self.op)),
+fn eval_if(scope: &mut Scope, stat: &ast::IfStat) -> Flow {
+    if eval_condition(scope, &stat.condition) {
+        stat.if_block.eval(scope)
+    } else {
+        match &stat.else_block {
+            Some(ast::ElseBlock::Elif(x)) => eval_if(scope, x),
+            Some(ast::ElseBlock::Else(x)) => x.eval(scope),
+            None => Flow::Next,
         }
     }
 }
 
-impl parser::Nodeable for parser::IntLiteral {
-    fn eval(&self, _: &mut Scope) -> Obj {
-        //note that: int literal may only be unsigned,
-        //while object ints can be signed
-        Obj::Int(self.value as i64)
+//runs a block of a `try`, stopping at errors.
+//a `?` in it only unwinds this far, and returns from the function the same as a `return` would
+fn eval_guarded(scope: &mut Scope, block: &ast::StatSeq) -> Result<Flow, RuntimeError> {
+    match stop_unwinding(|| block.eval(scope)) {
+        Ok(x) => Ok(x),
+        Err(Unwind::Error(x)) => Err(x),
+        Err(Unwind::Propagate(x)) => Ok(Flow::Return(Obj::Error(x))),
     }
 }
 
-impl parser::Nodeable for parser::FloatLiteral {
-    fn eval(&self, _: &mut Scope) -> Obj {
-        Obj::Float(self.value)
-    }
-}
+//`try { ... } catch (e) { ... } finally { ... }`. the blocks share the scope around them,
+//the error is only defined while the `catch` block runs.
+//`finally` runs however the rest ended, a `return` in it wins over the one before
+fn eval_try(scope: &mut Scope, body: &ast::StatSeq, catch_block: &Option<ast::Catch>, finally: &Option<ast::StatSeq>) -> Flow {
+    let result = match (eval_guarded(scope, body), catch_block) {
+        (Err(error), Some(catch_block)) => {
+            let outer = scope.vars.insert(catch_block.name.clone(), Obj::Error(Rc::new(error)));
+            let result = eval_guarded(scope, &catch_block.body);
+            match outer {
+                Some(x) => scope.vars.insert(catch_block.name.clone(), x),
+                None => scope.vars.remove(&catch_block.name),
+            };
+            result
+        },
+        (result, _) => result,
+    };
 
-impl parser::Nodeable for parser::StrLiteral {
-    fn eval(&self, _: &mut Scope) -> Obj {
-        Obj::String(self.value.clone())
+    if let Some(finally) = finally {
+        match finally.eval(scope) {
+            Flow::Next => {},
+            x => return x,
+        }
+    }
+    match result {
+        Ok(flow) => flow,
+        Err(error) => raise(error),
     }
 }
 
-impl parser::Nodeable for parser::Variable {
-    fn eval(&self, scope: &mut Scope) -> Obj {
-        let vars = &scope.vars;
-        let Some(value) = vars.get(&self.name) else {
-            error(format!("Variable of name {} is not defined in scope.", self.name));
-        };
-        value.clone()
+impl ast::Stmt {
+    pub fn eval(&self, scope: &mut Scope) -> Flow {
+        LOCATION.with_borrow_mut(|x| x.line = self.span.line);
+        if let Some(hooks) = scope.hooks.clone() {
+            hooks.borrow_mut().stmt(self, scope);
+        }
+
+        match &self.kind {
+            ast::StmtKind::Import { mod_name } => {
+                let _ = mod_name;
+                //built-in's are compiled into execute,
+                //so "importing" doesn't have semantic meaning.
+                //there is not to "load" into memory.
+            },
+            ast::StmtKind::Assign { var_name, op, expr } => eval_assign(scope, var_name, *op, expr),
+            ast::StmtKind::Return { expr: Some(ast::Expr { kind: ast::ExprKind::Call(call), .. }), tail_call: true } => {
+                //the call itself is left to `eval_call` of the running function
+                let mut arg_vals: Vec<Obj> = vec![];
+                for arg in &call.args {
+                    arg_vals.push(arg.eval(scope));
+                }
+                return Flow::TailCall(arg_vals);
+            },
+            ast::StmtKind::Return { expr, .. } => {
+                let value = match expr {
+                    Some(expr) => expr.eval(scope),
+                    None => Obj::Nil,
+                };
+                return Flow::Return(value);
+            },
+            ast::StmtKind::Fun(decl) => {
+                scope.funs.insert(decl.name.clone(), Fun::new(decl.clone(), self.span));
+            },
+            ast::StmtKind::Expr(expr) => { expr.eval(scope); },
+            ast::StmtKind::If(stat) => return eval_if(scope, stat),
+            ast::StmtKind::While { condition, body } => {
+                while eval_condition(scope, condition) {
+                    match body.eval(scope) {
+                        Flow::Next => {},
+                        x => return x,
+                    }
+                }
+            },
+            ast::StmtKind::For { elem_name, array, body, .. } => {
+                let arr = match array.eval(scope) {
+                    Obj::Array(x) => x,
+                    x => error(ERR_TYPE, format!("Unable to iterate non-array value {:?}.", x)),
+                };
+
+                for elem in arr {
+                    let mut inner_scope = scope.clone();
+                    inner_scope.vars.insert(elem_name.clone(), elem);
+
+                    match body.eval(&mut inner_scope) {
+                        Flow::Next => {},
+                        x => return x,
+                    }
+                }
+            },
+            ast::StmtKind::Try { body, catch, finally } => return eval_try(scope, body, catch, finally),
+            ast::StmtKind::Error => {
+                //programs with syntax errors are never run
+                error(ERR_SYNTAX, format!("Cannot evaluate code with syntax errors (line {}).", self.span.line));
+            },
+        }
+
+        Flow::Next
     }
 }
 
-impl parser::Nodeable for parser::FunctionCall {
-    fn eval(&self, scope: &mut Scope) -> Obj {
 
-        //pre-evaluate argument expressions
-        let mut arg_vals: Vec<Obj> = vec![];
-        for arg in &self.args {
-            arg_vals.push(arg.eval(scope));
-        }
+//f.e. "2", "1 to 2" or "at least 1"
+pub fn describe_arity(decl: &ast::FunctionDeclare) -> String {
+    let max = decl.args.len();
+    let min = decl.args.iter().filter(|x| x.default.is_none()).count();
+    if decl.rest.is_some() { return format!("at least {}", min) }
+    if min == max { return format!("{}", min) }
+    format!("{} to {}", min, max)
+}
 
-        let funs = &scope.funs;
-        let Some(fun) = funs.get(&self.name) else {
-            error(format!("Function of name {} is not declared in scope.", self.name));
-        };
+fn eval_call(scope: &mut Scope, call: &ast::FunctionCall, line: u32) -> Obj {
 
-        //explicit clone to enable scope teardown
-        let mut inner_scope = scope.clone();
+    //pre-evaluate argument expressions
+    let mut arg_vals: Vec<Obj> = vec![];
+    for arg in &call.args {
+        arg_vals.push(arg.eval(scope));
+    }
 
-        //shouldn't be set anyways
-        inner_scope.ret_val = Obj::Invalid;
-        inner_scope.ret_flag = false; 
+    call_function(scope, &call.name, arg_vals, line)
+}
 
-        //inject args
-        for (arg_val, arg_name) in std::iter::zip(arg_vals, &fun.args) {
-            inner_scope.vars.insert(arg_name.clone(), arg_val);
-        }
+//calls the oko function `name` as if from `line`, which is 0 when the call doesn't come from oko code
+pub fn call_function(scope: &mut Scope, name: &str, arg_vals: Vec<Obj>, line: u32) -> Obj {
+    let funs = &scope.funs;
+    let Some(fun) = funs.get(name) else {
+        error(ERR_NAME, format!("Function of name {} is not declared in scope.", name));
+    };
+    let decl = fun.decl.clone();
+    let declared = fun.span;
+    let propagates = fun.propagates;
+
+    let depth = scope.frame.as_ref().map_or(0, |x| x.depth) + 1;
+    if depth > scope.max_call_depth {
+        error(ERR_DEPTH, format!("Maximum call stack size exceeded, functions may only nest {} calls deep.", scope.max_call_depth));
+    }
+    if out_of_stack() {
+        error(ERR_DEPTH, format!("Maximum call stack size exceeded, the interpreter ran out of stack after {} calls.", depth - 1));
+    }
+
+    //explicit clone to enable scope teardown
+    let mut inner_scope = scope.clone();
+    inner_scope.frame = Some(Rc::new(Frame { name: name.to_string(), declared, line, depth, parent: scope.frame.clone() }));
+    if let (Some(hooks), Some(frame)) = (&scope.hooks, &inner_scope.frame) {
+        hooks.borrow_mut().entered(frame);
+    }
 
-        fun.body.eval(&mut inner_scope);
+    let frame = inner_scope.frame.clone();
+    let caller = LOCATION.replace(Location { line, frame: frame.clone() });
+    //a `?` in the function unwinds back to here, with the error it returns
+    let value = match propagates {
+        false => run_function(inner_scope, name, &decl, arg_vals),
+        true => match stop_unwinding(|| run_function(inner_scope, name, &decl, arg_vals)) {
+            Ok(x) => x,
+            Err(Unwind::Propagate(x)) => Obj::Error(x),
+            Err(Unwind::Error(x)) => raise(x),
+        },
+    };
 
-        inner_scope.ret_val
+    LOCATION.set(caller);
+    if let (Some(hooks), Some(frame)) = (scope.hooks.clone(), &frame) {
+        hooks.borrow_mut().returned(frame, &value, scope);
     }
+    value
 }
 
-impl parser::Nodeable for parser::ModAccess {
-    fn eval(&self, scope: &mut Scope) -> Obj {
-        match (self.mod_name.as_str(), self.member.name.as_str()) {
-            ("io", "println") => println!("dbg out: {}", self.member.args[0].eval(scope)),
-            _ => todo!(),
-        };
-        Obj::Invalid
+//runs the body of a call until it returns
+fn run_function(mut inner_scope: Scope, name: &str, decl: &ast::FunctionDeclare, arg_vals: Vec<Obj>) -> Obj {
+    enter_function(&mut inner_scope, name, decl, arg_vals);
+    let mut flow = decl.body.eval(&mut inner_scope);
+
+    loop {
+        match flow {
+            Flow::Return(value) => return value,
+            Flow::Next => return Obj::Nil, //no `return`, or `return;` at the end
+            //a tail call restarts the function in place, with the scope the nested call would have gotten
+            Flow::TailCall(arg_vals) => {
+                let mut next_scope = inner_scope.clone();
+                enter_function(&mut next_scope, name, decl, arg_vals);
+                flow = decl.body.eval(&mut next_scope);
+                inner_scope = next_scope;
+            },
+        }
     }
 }
 
-impl parser::Nodeable for parser::ArrayLiteral {
-    fn eval(&self, scope: &mut self::Scope) -> Obj {
-        Obj::Array(
-            self.elem
-                .iter()
-                .map(|x| x.eval(scope))
-                .collect()
-        )
+//checks the arguments and binds them to the parameters
+fn enter_function(inner_scope: &mut Scope, name: &str, decl: &ast::FunctionDeclare, arg_vals: Vec<Obj>) {
+    let required = decl.args.iter().filter(|x| x.default.is_none()).count();
+    if arg_vals.len() < required || (decl.rest.is_none() && arg_vals.len() > decl.args.len()) {
+        error(ERR_ARGS, format!(
+            "Function {} expects {} argument(s), but got {}.",
+            name, describe_arity(decl), arg_vals.len(),
+        ));
+    }
+
+    //inject args, extra ones go to the rest parameter
+    let mut arg_vals = arg_vals.into_iter();
+    for param in &decl.args {
+        let value = match (arg_vals.next(), &param.default) {
+            (Some(x), _) => x,
+            //defaults are evaluated on every call, and can refer to the parameters before them
+            (None, Some(default)) => default.eval(inner_scope),
+            (None, None) => unreachable!(),
+        };
+        inner_scope.vars.insert(param.name.clone(), value);
+    }
+    if let Some(rest) = &decl.rest {
+        inner_scope.vars.insert(rest.name.clone(), Obj::Array(arg_vals.collect()));
     }
 }
 
-impl parser::Nodeable for parser::ReturnStat {
-    fn eval(&self, scope: &mut self::Scope) -> Obj {
-        if let Some(expr) = &self.expr {
-            scope.ret_val = expr.eval(scope);
+fn eval_mod_access(scope: &mut Scope, mod_name: &str, member: &ast::FunctionCall) -> Obj {
+    let Some(builtin) = builtins::lookup(mod_name, &member.name) else {
+        error(ERR_NAME, format!("Module {} has no function named {}.", mod_name, member.name));
+    };
+
+    if let Some(arity) = builtin.arity {
+        if member.args.len() != arity {
+            error(ERR_ARGS, format!(
+                "Function {}::{} expects {} argument(s), but got {}.",
+                mod_name, member.name, arity, member.args.len(),
+            ));
         }
-        scope.ret_flag = true;
+    }
 
-        Obj::Invalid
+    let mut arg_vals: Vec<Obj> = vec![];
+    for arg in &member.args {
+        arg_vals.push(arg.eval(scope));
     }
+
+    (builtin.call)(scope, arg_vals)
 }
 
-impl parser::Nodeable for parser::FunctionDeclare {
-    fn eval(&self, scope: &mut self::Scope) -> Obj {
-        scope.funs.insert(self.name.clone(), Fun {
-            args: self.args.clone(),
-            body: self.body.clone(),
-        });
+impl ast::Expr {
+    pub fn eval(&self, scope: &mut Scope) -> Obj {
+        match &self.kind {
+            ast::ExprKind::Binary { op, left, right } => {
+                let left  = left.eval(scope);
+                let right = right.eval(scope);
 
-        Obj::Invalid
+                apply_binary_op(&left, &right, op.as_str())
+            },
+            ast::ExprKind::Unary { op, operand } => {
+                let expr = operand.eval(scope);
+                match (op.as_str(), expr) {
+                    ("!", Obj::Bool(x))   => Obj::Bool(!x),
+                    ("-", Obj::Int(x))    => checked(x.checked_neg(), "negation"),
+                    ("-", Obj::Float(x))  => Obj::Float(-x),
+                    _ => error(ERR_TYPE, format!("Unable to perform unary operator {} on given type.", op)),
+                }
+            },
+            ast::ExprKind::Int { value, .. } => {
+                //note that: int literal may only be unsigned,
+                //while object ints can be signed.
+                //the lexer rejects literals above i64::MAX, this is just a safety net
+                let Ok(value) = i64::try_from(*value) else {
+                    error(ERR_SYNTAX, format!("Integer literal {} is out of range.", value));
+                };
+                Obj::Int(value)
+            },
+            ast::ExprKind::Float { value, .. } => Obj::Float(*value),
+            ast::ExprKind::Str(value)   => Obj::String(value.clone()),
+            ast::ExprKind::Format(parts) => {
+                let mut out = String::new();
+                for part in parts {
+                    out += &part.eval(scope).to_string();
+                }
+                Obj::String(out)
+            },
+            ast::ExprKind::Variable(name) => {
+                let vars = &scope.vars;
+                let Some(value) = vars.get(name) else {
+                    error(ERR_NAME, format!("Variable of name {} is not defined in scope.", name));
+                };
+                value.clone()
+            },
+            ast::ExprKind::Call(call) => eval_call(scope, call, self.span.line),
+            ast::ExprKind::ModAccess { mod_name, member } => eval_mod_access(scope, mod_name, member),
+            ast::ExprKind::Array(elem) => {
+                Obj::Array(
+                    elem
+                        .iter()
+                        .map(|x| x.eval(scope))
+                        .collect()
+                )
+            },
+            ast::ExprKind::Propagate(expr) => match expr.eval(scope) {
+                //outside of functions there is nothing to return from, so the error is raised instead
+                Obj::Error(x) if scope.frame.is_none() => raise((*x).clone()),
+                Obj::Error(x) => unwind(Unwind::Propagate(x)),
+                x => x,
+            },
+            ast::ExprKind::Error => {
+                error(ERR_SYNTAX, format!("Cannot evaluate code with syntax errors (line {}).", self.span.line));
+            },
+        }
     }
 }
 
 
-impl parser::Nodeable for parser::ExprStat {
-    fn eval(&self, scope: &mut self::Scope) -> Obj {
-        self.expr.eval(scope);
-        Obj::Invalid
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    //runs `source`, giving the scope it ends with or the runtime error that nothing caught
+    fn run(source: &str) -> Result<Scope, RuntimeError> {
+        //runtime errors unwind as panics, the default hook would print each of them
+        static QUIET: std::sync::Once = std::sync::Once::new();
+        QUIET.call_once(|| {
+            let default_hook = std::panic::take_hook();
+            std::panic::set_hook(Box::new(move |info| {
+                if info.payload().is::<Unwinding>() { return }
+                default_hook(info);
+            }));
+        });
+
+        let mut stream = lexer::lex(source);
+        let root = parser::parse_program(&mut stream);
+        assert!(stream.diagnostics().is_empty(), "{:?}", stream.diagnostics());
+
+        let mut scope = Scope {
+            vars: HashMap::new(), funs: HashMap::new(), args: Rc::new(vec![]),
+            frame: None, max_call_depth: DEFAULT_MAX_CALL_DEPTH, hooks: None,
+        };
+        catch(|| root.eval(&mut scope))?;
+        Ok(scope)
     }
-}
 
-impl parser::Nodeable for parser::IfStat {
-    fn eval(&self, scope: &mut self::Scope) -> Obj {
-        let cond_val = self.condition.eval(scope);
+    //the code and message of the error `source` leaves in the variable `caught`
+    fn caught(source: &str) -> (String, String) {
+        let scope = run(source).unwrap_or_else(|x| panic!("{}", format_error(&x)));
+        let Some(Obj::Error(x)) = scope.vars.get("caught") else { panic!("Nothing was caught: {:?}", scope.vars.get("caught")) };
+        (x.code.clone(), x.msg.clone())
+    }
 
-        if truthiness(cond_val) {
-            self.if_block.eval(scope)
-        } else if let Some(else_block) = &self.else_block {
-            else_block.eval(scope)
-        } else {
-            Obj::Invalid
+    #[test]
+    fn integer_overflow_is_a_catchable_error() {
+        let cases = [
+            ("9223372036854775807 + 1", "addition"),
+            ("(-9223372036854775807 - 1) - 1", "subtraction"),
+            ("3037000500 * 3037000500", "multiplication"),
+            ("(-9223372036854775807 - 1) / -1", "division"),
+            ("(-9223372036854775807 - 1) % -1", "modulo"),
+            ("2 ^ 63", "exponentiation"),
+            ("-(-9223372036854775807 - 1)", "negation"),
+        ];
+        for (expr, what) in cases {
+            let source = format!("caught := 0;\ntry {{ x := {}; }} catch (e) {{ caught = e; }}", expr);
+            let msg = format!("The result of the {} doesn't fit into an integer.", what);
+            assert_eq!(caught(&source), (ERR_RANGE.to_string(), msg), "{}", expr);
         }
+
+        let source = "caught := 0;\ntry { x := 2 ^ -1; } catch (e) { caught = e; }";
+        assert_eq!(caught(source).0, ERR_RANGE);
     }
-}
 
-impl parser::Nodeable for parser::WhileStat {
-    fn eval(&self, scope: &mut self::Scope) -> Obj {
-        while truthiness(self.condition.eval(scope)) {
-            self.body.eval(scope);
-        }
-        
-        Obj::Invalid
+    #[test]
+    fn integer_arithmetic_up_to_the_limits() {
+        let scope = run("a := 9223372036854775806 + 1;\nb := 2 ^ 62;\nc := 1 ^ 5000000000;\nd := (-1) ^ 5000000001;\ne := -9223372036854775807 - 1;").ok().unwrap();
+        assert_eq!(scope.vars["a"], Obj::Int(i64::MAX));
+        assert_eq!(scope.vars["b"], Obj::Int(1 << 62));
+        assert_eq!(scope.vars["c"], Obj::Int(1));
+        assert_eq!(scope.vars["d"], Obj::Int(-1));
+        assert_eq!(scope.vars["e"], Obj::Int(i64::MIN));
     }
-}
 
-impl parser::Nodeable for parser::ForStat {
-    fn eval(&self, scope: &mut self::Scope) -> self::Obj {
-        let Obj::Array(arr) = self.array.eval(scope) else {
-            error("Unable to iterate non-array type.".to_string());
-        };
+    #[test]
+    fn try_catches_runtime_errors() {
+        let source = "fun divide(a, b) { return a / b; }\ncaught := 0;\ntry { divide(1, 0); } catch (e) { caught = e; }";
+        assert_eq!(caught(source), (ERR_ZERO.to_string(), "Unable to divide an integer by zero.".to_string()));
 
-        for elem in arr {
-            let mut inner_scope = scope.clone();
-            inner_scope.vars.insert(self.elem_name.clone(), elem);
+        let source = "import prog;\ncaught := 0;\ntry { prog::throw(\"no\", \"mine\"); } catch (e) { caught = e; }";
+        assert_eq!(caught(source), ("mine".to_string(), "no".to_string()));
+    }
 
-            self.body.eval(&mut inner_scope);
-        }
+    #[test]
+    fn finally_runs_on_return_and_on_rethrow() {
+        //an error raised by `finally` replaces the value `try` was about to return
+        let source = "import prog;\nfun f() { try { return 1; } finally { prog::throw(\"finally ran\"); } }\ncaught := 0;\ntry { f(); } catch (e) { caught = e; }";
+        assert_eq!(caught(source).1, "finally ran");
 
-        Obj::Invalid
+        let scope = run("fun f() { try { return 1; } finally { x := 2; } }\nr := f();").ok().unwrap();
+        assert_eq!(scope.vars["r"], Obj::Int(1));
+
+        let source = "import prog;\nran := 0;\ncaught := 0;\ntry { try { prog::throw(\"a\"); } catch (e) { prog::throw(e); } finally { ran = 1; } } catch (e) { caught = e; }";
+        let scope = run(source).ok().unwrap();
+        assert_eq!(scope.vars["ran"], Obj::Int(1));
+        assert!(matches!(&scope.vars["caught"], Obj::Error(x) if x.msg == "a" && x.code == ERR_THROWN));
+
+        let error = run("import prog;\ntry { prog::throw(\"a\"); } finally { x := 1; }").err().unwrap();
+        assert_eq!(error.msg, "a");
     }
-}
 
+    #[test]
+    fn catch_variable_is_restored_afterwards() {
+        let scope = run("import prog;\ne := 5;\ntry { prog::throw(\"a\"); } catch (e) { inner := e; }").ok().unwrap();
+        assert_eq!(scope.vars["e"], Obj::Int(5));
 
+        let scope = run("import prog;\ntry { prog::throw(\"a\"); } catch (err) {}").ok().unwrap();
+        assert!(!scope.vars.contains_key("err"));
+    }
+
+    #[test]
+    fn uncaught_errors_have_a_stack_trace() {
+        let source = "fun inner(x) {\n    return x / 0;\n}\nfun outer() {\n    return inner(1);\n}\nouter();";
+        let error = run(source).err().unwrap();
+        assert_eq!(format_error(&error), [
+            "Runtime Error at line 2: Unable to divide an integer by zero.",
+            "Call stack (innermost first):",
+            "    at inner (line 5)",
+            "    at outer (line 7)",
+            "    at <program>",
+        ].join("\n"));
+    }
 
+    #[test]
+    fn question_mark_returns_errors_from_the_function() {
+        let source = "import tu;\nfun parse(s) { n := tu::toNumber(s)?; return n + 1; }\nfun twice(s) { n := parse(s)?; return n * 2; }\na := twice(\"20\");\nb := twice(\"abc\");";
+        let scope = run(source).ok().unwrap();
+        assert_eq!(scope.vars["a"], Obj::Int(42));
+        let Obj::Error(x) = &scope.vars["b"] else { panic!("Expected an error: {:?}", scope.vars["b"]) };
+        assert_eq!(x.code, ERR_INVALID_NUMBER);
+        assert_eq!(x.msg, "Unable to read a number from \"abc\".");
+    }
+}
 
@@ -0,0 +1,363 @@
+
+use crate::ast::{ElseBlock, Expr, ExprKind, FunctionCall, FunctionDeclare, IfStat, Param, StatSeq, Stmt, StmtKind};
+use crate::lexer::Span;
+use crate::parser::get_op_precedence;
+
+
+//the pretty-printer behind `oko fmt`. it prints the syntax tree in the canonical style:
+// - four spaces of indentation, opening braces on the same line, `} elif (...) {` and `} else {`
+// - spaces around binary operators and after commas, a semicolon after every simple statement
+// - parentheses only where the tree needs them
+// - at most one blank line between statements, none at the start of a block
+//comments are kept. the ones in between statements stay where they are,
+//the ones inside of an expression move in front of their statement.
+//number literals are kept as they were written, so `0xFF` stays `0xFF`.
+
+
+//unary operators bind tighter than any binary one
+const UNARY_PRECEDENCE: u32 = 9;
+
+fn precedence(expr: &Expr) -> u32 {
+    match &expr.kind {
+        ExprKind::Binary { op, .. } => get_op_precedence(op),
+        _ => u32::MAX,
+    }
+}
+
+fn line_breaks(text: &str) -> u32 {
+    text.matches('\n').count() as u32
+}
+
+
+struct Formatter<'a> {
+    source: &'a str,
+    comments: &'a [Span],
+    next_comment: usize, //comments before this one have been written already
+    out: String,
+    indent: usize,
+    last_line: u32, //the source line the last written statement or comment ended on
+    block_start: bool, //nothing has been written into the current block yet
+}
+
+impl<'a> Formatter<'a> {
+    fn text(&self, span: Span) -> &'a str {
+        &self.source[span.offset..span.offset + span.len]
+    }
+
+    fn end_line(&self, span: Span) -> u32 {
+        span.line + line_breaks(self.text(span))
+    }
+
+    fn start_line(&mut self) {
+        self.out.push_str(&"    ".repeat(self.indent));
+    }
+
+    //keeps one blank line where the source had at least one
+    fn separate(&mut self, line: u32) {
+        if !self.block_start && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+        self.block_start = false;
+    }
+
+    fn peek_comment(&self) -> Option<Span> {
+        self.comments.get(self.next_comment).copied()
+    }
+
+    fn write_comment(&mut self, span: Span, moved: bool) {
+        if !moved { self.separate(span.line); }
+        self.start_line();
+        self.out.push_str(self.text(span).trim_end());
+        self.out.push('\n');
+        self.last_line = self.end_line(span);
+        self.next_comment += 1;
+    }
+
+    //`moved` comments are taken out of an expression, blank lines around them don't matter
+    fn comments_before(&mut self, offset: usize, moved: bool) {
+        while let Some(span) = self.peek_comment() {
+            if span.offset >= offset { break }
+            self.write_comment(span, moved);
+        }
+    }
+
+    //finishes the current line, pulling up a comment that followed `span` on the same line
+    fn end_statement(&mut self, span: Span) {
+        let line = self.end_line(span);
+        if let Some(comment) = self.peek_comment() {
+            if comment.line == line && !self.text(comment).contains('\n') {
+                self.out.push(' ');
+                self.out.push_str(self.text(comment).trim_end());
+                self.next_comment += 1;
+            }
+        }
+        self.out.push('\n');
+        self.last_line = line;
+    }
+
+
+    //writes `{ ... }`, the caller has written everything up to the brace and finishes the line
+    fn block(&mut self, block: &StatSeq) {
+        let end = block.span.offset + block.span.len;
+        let has_comments = self.peek_comment().is_some_and(|x| x.offset < end);
+        if block.stmts.is_empty() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push_str("{\n");
+        self.indent += 1;
+        self.block_start = true;
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        self.comments_before(end, false);
+        self.indent -= 1;
+        self.block_start = false;
+
+        self.start_line();
+        self.out.push('}');
+    }
+
+    fn if_stat(&mut self, stat: &IfStat) {
+        self.out.push_str(&format!("({}) ", self.expr(&stat.condition)));
+        self.block(&stat.if_block);
+        match &stat.else_block {
+            Some(ElseBlock::Elif(x)) => {
+                self.out.push_str(" elif ");
+                self.if_stat(x);
+            },
+            Some(ElseBlock::Else(x)) => {
+                self.out.push_str(" else ");
+                self.block(x);
+            },
+            None => {},
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.comments_before(stmt.span.offset, false);
+        self.separate(stmt.span.line);
+
+        //comments inside of expressions, the ones in blocks are written along with the block
+        let header_end = match &stmt.kind {
+            StmtKind::Fun(decl) => decl.body.span.offset,
+            StmtKind::If(x) => x.if_block.span.offset,
+            StmtKind::While { body, .. } | StmtKind::For { body, .. } | StmtKind::Try { body, .. } => body.span.offset,
+            _ => stmt.span.offset + stmt.span.len,
+        };
+        self.comments_before(header_end, true);
+
+        self.start_line();
+        match &stmt.kind {
+            StmtKind::Import { mod_name } => self.out.push_str(&format!("import {};", mod_name)),
+            StmtKind::Assign { var_name, op, expr } => {
+                self.out.push_str(&format!("{} {} {};", var_name, op, self.expr(expr)));
+            },
+            StmtKind::Return { expr: Some(x), .. } => self.out.push_str(&format!("return {};", self.expr(x))),
+            StmtKind::Return { expr: None, .. } => self.out.push_str("return;"),
+            StmtKind::Expr(x) => self.out.push_str(&format!("{};", self.expr(x))),
+            StmtKind::Fun(decl) => {
+                self.out.push_str(&format!("{} ", self.signature(decl)));
+                self.block(&decl.body);
+            },
+            StmtKind::If(x) => {
+                self.out.push_str("if ");
+                self.if_stat(x);
+            },
+            StmtKind::While { condition, body } => {
+                self.out.push_str(&format!("while ({}) ", self.expr(condition)));
+                self.block(body);
+            },
+            StmtKind::For { elem_name, array, body, .. } => {
+                self.out.push_str(&format!("for ({}) ({}) ", elem_name, self.expr(array)));
+                self.block(body);
+            },
+            StmtKind::Try { body, catch, finally } => {
+                self.out.push_str("try ");
+                self.block(body);
+                if let Some(x) = catch {
+                    self.out.push_str(&format!(" catch ({}) ", x.name));
+                    self.block(&x.body);
+                }
+                if let Some(x) = finally {
+                    self.out.push_str(" finally ");
+                    self.block(x);
+                }
+            },
+            StmtKind::Error => unreachable!("Programs with syntax errors are never formatted."),
+        }
+        self.end_statement(stmt.span);
+    }
+
+
+    //`fun name(a, b := 1, ...rest)`
+    fn signature(&self, decl: &FunctionDeclare) -> String {
+        let mut params: Vec<String> = decl.args.iter().map(|x| self.param(x)).collect();
+        if let Some(rest) = &decl.rest {
+            params.push(format!("...{}", rest.name));
+        }
+        format!("fun {}({})", decl.name, params.join(", "))
+    }
+
+    fn param(&self, param: &Param) -> String {
+        match &param.default {
+            Some(x) => format!("{} := {}", param.name, self.expr(x)),
+            None => param.name.clone(),
+        }
+    }
+
+    fn args(&self, call: &FunctionCall) -> String {
+        let args: Vec<String> = call.args.iter().map(|x| self.expr(x)).collect();
+        args.join(", ")
+    }
+
+    fn wrap(&self, expr: &Expr, parens: bool) -> String {
+        if parens { format!("({})", self.expr(expr)) } else { self.expr(expr) }
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            //equal precedence chains to the right, so only the left side needs parentheses then
+            ExprKind::Binary { op, left, right } => {
+                let prec = get_op_precedence(op);
+                format!("{} {} {}", self.wrap(left, precedence(left) <= prec), op, self.wrap(right, precedence(right) < prec))
+            },
+            ExprKind::Unary { op, operand } => {
+                let parens = precedence(operand) < UNARY_PRECEDENCE || matches!(operand.kind, ExprKind::Unary { .. });
+                format!("{}{}", op, self.wrap(operand, parens))
+            },
+            ExprKind::Propagate(x) => {
+                format!("{}?", self.wrap(x, matches!(x.kind, ExprKind::Binary { .. } | ExprKind::Unary { .. })))
+            },
+            ExprKind::Int { text, .. } | ExprKind::Float { text, .. } => text.clone(),
+            ExprKind::Str(x) => format!("\"{}\"", escape(x)),
+            ExprKind::Format(parts) => {
+                let mut out = String::from("\"");
+                for part in parts {
+                    match &part.kind {
+                        ExprKind::Str(x) => out.push_str(&escape(x)),
+                        //quotes of strings inside of it would end the literal otherwise
+                        _ => out.push_str(&format!("{{{}}}", self.expr(part).replace('"', "\\\""))),
+                    }
+                }
+                out.push('"');
+                out
+            },
+            ExprKind::Variable(x) => x.clone(),
+            ExprKind::Call(call) => format!("{}({})", call.name, self.args(call)),
+            ExprKind::ModAccess { mod_name, member } => format!("{}::{}({})", mod_name, member.name, self.args(member)),
+            ExprKind::Array(elems) => {
+                let elems: Vec<String> = elems.iter().map(|x| self.expr(x)).collect();
+                format!("[{}]", elems.join(", "))
+            },
+            ExprKind::Error => unreachable!("Programs with syntax errors are never formatted."),
+        }
+    }
+}
+
+//turns the contents of a string literal back into source, the reverse of the lexer's escape sequences
+fn escape(value: &str) -> String {
+    let mut out = String::new();
+    for char in value.chars() {
+        match char {
+            '\n'   => out.push_str("\\n"),
+            '\t'   => out.push_str("\\t"),
+            '\x1B' => out.push_str("\\e"),
+            '"'    => out.push_str("\\\""),
+            '{'    => out.push_str("{{"),
+            '}'    => out.push_str("}}"),
+            x      => out.push(x),
+        }
+    }
+    out
+}
+
+
+fn formatter<'a>(source: &'a str, comments: &'a [Span]) -> Formatter<'a> {
+    Formatter { source, comments, next_comment: 0, out: String::new(), indent: 0, last_line: 0, block_start: true }
+}
+
+//`root` has to be free of syntax errors
+pub fn format(source: &str, comments: &[Span], root: &StatSeq) -> String {
+    let mut formatter = formatter(source, comments);
+    for stmt in &root.stmts {
+        formatter.stmt(stmt);
+    }
+    formatter.comments_before(usize::MAX, false);
+    formatter.out
+}
+
+//the first line of a function declaration in the canonical style, for editors to show
+pub fn signature(source: &str, decl: &FunctionDeclare) -> String {
+    formatter(source, &[]).signature(decl)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dump, lexer, parser};
+
+    fn format_source(source: &str) -> String {
+        let mut stream = lexer::lex(source);
+        let root = parser::parse_program(&mut stream);
+        assert!(stream.diagnostics().is_empty(), "{:?}", stream.diagnostics());
+        format(source, stream.comments(), &root)
+    }
+
+    //the syntax tree without positions, which formatting is allowed to change
+    fn shape(source: &str) -> String {
+        let mut stream = lexer::lex(source);
+        let root = parser::parse_program(&mut stream);
+        assert!(stream.diagnostics().is_empty(), "{:?}", stream.diagnostics());
+        let tree = dump::tree(&dump::block(&root));
+        tree.lines().map(|x| x.split(' ').filter(|x| !x.starts_with('@')).collect::<Vec<_>>().join(" ")).collect::<Vec<_>>().join("\n")
+    }
+
+    //formatting must keep the meaning, and formatting the result again must not change it
+    fn round_trip(source: &str) -> String {
+        let formatted = format_source(source);
+        assert_eq!(shape(&formatted), shape(source), "{}", formatted);
+        assert_eq!(format_source(&formatted), formatted);
+        formatted
+    }
+
+    #[test]
+    fn example_programs() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/prg");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|x| x != "oko") { continue }
+            round_trip(&std::fs::read_to_string(&path).unwrap());
+        }
+    }
+
+    #[test]
+    fn number_literals_keep_their_spelling() {
+        assert_eq!(round_trip("a := (0xFF)+1_000*0b1;"), "a := 0xFF + 1_000 * 0b1;\n");
+        assert_eq!(round_trip("a := [.5, 1e3, 2.5E-1, 0o17];"), "a := [.5, 1e3, 2.5E-1, 0o17];\n");
+    }
+
+    #[test]
+    fn interpolations_after_escape_sequences() {
+        assert_eq!(round_trip("io::println(\"\\t{0xFF} x\");"), "io::println(\"\\t{0xFF} x\");\n");
+        assert_eq!(round_trip("io::println(\"\\\"é\\\"{(1)+2}\\n{{}}{ f(\\\"a\\\") }\");"),
+                   "io::println(\"\\\"é\\\"{1 + 2}\\n{{}}{f(\\\"a\\\")}\");\n");
+        assert_eq!(round_trip("a := \"\\e[1m{b}\\e[0m\";"), "a := \"\\e[1m{b}\\e[0m\";\n");
+    }
+
+    #[test]
+    fn parentheses_only_where_needed() {
+        assert_eq!(round_trip("a := (1 + 2) * -(3) - (4 - 5);"), "a := (1 + 2) * -3 - 4 - 5;\n"); //equal precedence chains to the right
+        assert_eq!(round_trip("a := (1 - 2) - 3;"), "a := (1 - 2) - 3;\n");
+        assert_eq!(round_trip("a := -(f()?) + (1 + 2)?;"), "a := -f()? + (1 + 2)?;\n");
+    }
+
+    #[test]
+    fn blocks_and_comments() {
+        let source = "fun f(a,b:=1,...c){\n\n//note\nif(a){return b;}elif(c){return;}else{}\n\n\n/* end */\n}\n";
+        assert_eq!(round_trip(source), "fun f(a, b := 1, ...c) {\n    //note\n    if (a) {\n        return b;\n    } elif (c) {\n        return;\n    } else {}\n\n    /* end */\n}\n");
+        assert_eq!(round_trip("try{\na();}catch(e){}finally{b();\n} // done\n"), "try {\n    a();\n} catch (e) {} finally {\n    b();\n} // done\n");
+    }
+}
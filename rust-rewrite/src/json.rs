@@ -0,0 +1,327 @@
+
+use core::fmt;
+
+
+//a minimal json value, enough to dump the syntax tree for other tools
+//and to talk to editors over the language server protocol.
+//objects keep their keys in insertion order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub fn str(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        let Json::Object(fields) = self else { return None };
+        fields.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        let Json::String(x) = self else { return None };
+        Some(x)
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        let Json::Number(x) = *self else { return None };
+        Some(x)
+    }
+
+    pub fn is_scalar(&self) -> bool {
+        !matches!(self, Json::Array(_) | Json::Object(_))
+    }
+
+    //same as `to_string`, but spread over multiple indented lines
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
+    }
+
+    fn write_pretty(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+        match self {
+            Json::Array(x) if !x.is_empty() => {
+                out.push_str("[\n");
+                for (i, elem) in x.iter().enumerate() {
+                    out.push_str(&indent);
+                    elem.write_pretty(out, depth + 1);
+                    out.push_str(if i + 1 < x.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(depth));
+                out.push(']');
+            },
+            Json::Object(x) if !x.is_empty() => {
+                out.push_str("{\n");
+                for (i, (key, value)) in x.iter().enumerate() {
+                    out.push_str(&indent);
+                    out.push_str(&format!("{}: ", Json::String(key.clone())));
+                    value.write_pretty(out, depth + 1);
+                    out.push_str(if i + 1 < x.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(depth));
+                out.push('}');
+            },
+            x => out.push_str(&x.to_string()),
+        }
+    }
+}
+
+
+fn write_str(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for char in value.chars() {
+        match char {
+            '"'  => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            x if (x as u32) < 0x20 => write!(f, "\\u{:04x}", x as u32)?,
+            x => write!(f, "{}", x)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null      => write!(f, "null"),
+            Json::Bool(x)   => write!(f, "{}", x),
+            //integral numbers are printed without a fraction, like javascript does
+            Json::Number(x) if x.fract() == 0.0 && x.abs() < 1e15 => write!(f, "{}", *x as i64),
+            Json::Number(x) if x.is_finite() => write!(f, "{}", x),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(x) => write_str(f, x),
+            Json::Array(x)  => {
+                write!(f, "[")?;
+                for (i, elem) in x.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write!(f, "{}", elem)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(x) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in x.iter().enumerate() {
+                    if i > 0 { write!(f, ",")?; }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+
+//reads json text one character at a time, same as the lexer does with oko source
+struct Parser {
+    chars: Vec<char>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.index).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|x| x.is_ascii_whitespace()) {
+            self.index += 1;
+        }
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, String> {
+        Err(format!("{} at character {} of the json text.", msg, self.index))
+    }
+
+    fn expect(&mut self, char: char) -> Result<(), String> {
+        if self.peek() != Some(char) {
+            return self.error(&format!("Expected '{}'", char));
+        }
+        self.index += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for char in word.chars() {
+            self.expect(char)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some('n') => self.keyword("null", Json::Null)?,
+            Some('t') => self.keyword("true", Json::Bool(true))?,
+            Some('f') => self.keyword("false", Json::Bool(false))?,
+            Some('"') => Json::String(self.string()?),
+            Some('[') => self.array()?,
+            Some('{') => self.object()?,
+            Some(x) if x == '-' || x.is_ascii_digit() => self.number()?,
+            Some(x) => return self.error(&format!("Unexpected '{}'", x)),
+            None => return self.error("Unexpected end"),
+        };
+        self.skip_whitespace();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.index;
+        while self.peek().is_some_and(|x| x.is_ascii_digit() || "+-.eE".contains(x)) {
+            self.index += 1;
+        }
+        let text: String = self.chars[start..self.index].iter().collect();
+        match text.parse::<f64>() {
+            Ok(x) => Ok(Json::Number(x)),
+            Err(_) => self.error(&format!("Invalid number '{}'", text)),
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        //checked by hand, `from_str_radix` would take a sign or fewer digits at the end of the text
+        let digits: String = self.chars.iter().skip(self.index).take(4).collect();
+        if digits.len() != 4 || !digits.chars().all(|x| x.is_ascii_hexdigit()) {
+            return self.error("Expected four hex digits after \\u");
+        }
+        let x = u32::from_str_radix(&digits, 16).unwrap();
+        self.index += 4;
+        Ok(x)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let Some(char) = self.peek() else { return self.error("Unterminated string") };
+            self.index += 1;
+            match char {
+                '"' => return Ok(out),
+                '\\' => {
+                    let Some(escaped) = self.peek() else { return self.error("Unterminated string") };
+                    self.index += 1;
+                    match escaped {
+                        'n' => out.push('\n'),
+                        't' => out.push('\t'),
+                        'r' => out.push('\r'),
+                        'b' => out.push('\x08'),
+                        'f' => out.push('\x0C'),
+                        'u' => {
+                            //characters outside of the basic plane come as a pair of surrogates
+                            let mut code = self.hex4()?;
+                            if (0xD800..0xDC00).contains(&code) && self.chars.get(self.index..self.index + 2) == Some(&['\\', 'u']) {
+                                self.index += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        },
+                        x => out.push(x), //`\"`, `\\` and `\/`
+                    }
+                },
+                x => out.push(x),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut elems = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.index += 1;
+            return Ok(Json::Array(elems));
+        }
+        loop {
+            elems.push(self.value()?);
+            match self.peek() {
+                Some(',') => self.index += 1,
+                Some(']') => { self.index += 1; return Ok(Json::Array(elems)) },
+                _ => return self.error("Expected ',' or ']'"),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.index += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            match self.peek() {
+                Some(',') => self.index += 1,
+                Some('}') => { self.index += 1; return Ok(Json::Object(fields)) },
+                _ => return self.error("Expected ',' or '}'"),
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser { chars: text.chars().collect(), index: 0 };
+    let value = parser.value()?;
+    if parser.index < parser.chars.len() {
+        return parser.error("Unexpected text after the value");
+    }
+    Ok(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_a_round_trip() {
+        let value = Json::object(vec![
+            ("null", Json::Null),
+            ("bools", Json::Array(vec![Json::Bool(true), Json::Bool(false)])),
+            ("numbers", Json::Array(vec![Json::Number(0.0), Json::Number(-12.0), Json::Number(2.5), Json::Number(1e300)])),
+            ("text", Json::str("\"quoted\" \\ \n\r\t \u{1} é 😀")),
+            ("nested", Json::object(vec![("empty", Json::Array(vec![])), ("", Json::object(vec![]))])),
+        ]);
+        assert_eq!(parse(&value.to_string()), Ok(value.clone()));
+        assert_eq!(parse(&value.pretty()), Ok(value));
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(parse(r#""éé 😀 \/\b\f""#), Ok(Json::str("éé 😀 /\x08\x0C")));
+        assert_eq!(parse(r#""\ud83d\ude00""#), Ok(Json::str("😀")));
+        assert_eq!(parse(r#" [1 , {"a" :2} ] "#), Ok(Json::Array(vec![Json::Number(1.0), Json::object(vec![("a", Json::Number(2.0))])])));
+    }
+
+    #[test]
+    fn malformed_text() {
+        assert_eq!(parse(r#""\u+123""#), Err("Expected four hex digits after \\u at character 3 of the json text.".to_string()));
+        assert_eq!(parse(r#""\u12"#), Err("Expected four hex digits after \\u at character 3 of the json text.".to_string()));
+        assert_eq!(parse(r#""\u 12a""#), Err("Expected four hex digits after \\u at character 3 of the json text.".to_string()));
+        assert_eq!(parse("[1,]"), Err("Unexpected ']' at character 3 of the json text.".to_string()));
+        assert_eq!(parse("{\"a\" 1}"), Err("Expected ':' at character 5 of the json text.".to_string()));
+        assert_eq!(parse("1 2"), Err("Unexpected text after the value at character 2 of the json text.".to_string()));
+        assert_eq!(parse("\"open"), Err("Unterminated string at character 5 of the json text.".to_string()));
+    }
+}
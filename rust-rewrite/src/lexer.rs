@@ -1,9 +1,11 @@
 use core::fmt;
+use std::num::IntErrorKind;
+use std::rc::Rc;
 
 
 
 
-const KEYWORDS: [&str; 8] = [
+const KEYWORDS: [&str; 11] = [
     "fun",
     "while",
     "if",
@@ -12,9 +14,12 @@ const KEYWORDS: [&str; 8] = [
     "import",
     "for",
     "return",
+    "try",
+    "catch",
+    "finally",
 ];
 
-const OPERATORS: [&str; 14] = [
+const OPERATORS: [&str; 15] = [
     "+",
     "-",
     "*",
@@ -29,32 +34,37 @@ const OPERATORS: [&str; 14] = [
     "&&",
     "||",
     "==",
+    "!=",
 ];
 
-const ESCAPE_SEQUENCES: [[&str; 2]; 4] = [
-    ["\\n", "\n"],
-    ["\\e", "\x1B"],
-    ["\\t", "\t"],
-    ["\\\"", "\""],
+//longer symbols come first, so that f.e. `:=` isn't split into `:` and `=`
+const SYMBOLS: [&str; 26] = [
+    "...",
+    "::", ":=", "+=", "-=", "*=", "/=", ">=", "<=", "==", "!=", "&&", "||",
+    "=", ",", ";", "+", "-", "*", "/", "%", "^", "!", ">", "<", "?",
 ];
 
+//the character after a `\\`, and the one the pair stands for
+const ESCAPE_SEQUENCES: [(char, char); 4] = [
+    ('n', '\n'),
+    ('e', '\x1B'),
+    ('t', '\t'),
+    ('"', '"'),
+];
 
-fn do_escape_sequences(mut seq: String) -> String
-{
-    for map in ESCAPE_SEQUENCES.iter() {
-        seq = seq.replace(map[0], map[1]);
-    }
 
-    return seq;
+//what `\\` followed by `char` stands for, other pairs are kept as they are
+pub fn escape_sequence(char: char) -> Option<char> {
+    ESCAPE_SEQUENCES.iter().find(|x| x.0 == char).map(|x| x.1)
 }
 
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum TokenClass {
     Operator(String),
-    String(String),
-    Integer(u64),
-    Float(f64),
+    String(String), //as written between the quotes, escape sequences and `{...}` are up to the parser
+    Integer { value: u64, text: String }, //`text` is the literal as written, f.e. `0xFF` or `1_000`
+    Float   { value: f64, text: String },
     Identifier(String),
     Keyword(String),
     EndOfStatement, // ;
@@ -66,6 +76,9 @@ pub enum TokenClass {
     CurlyOpen, CurlyClose,
     BracketOpen, BracketClose,
     Namespace, // ::
+    Ellipsis, // ...
+    Question, // ?
+    DocComment(String), // `/// ...`, kept for tooling, skipped by the parser
 }
 
 impl fmt::Display for TokenClass {
@@ -73,8 +86,8 @@ impl fmt::Display for TokenClass {
         match *self {
             Self::Operator(ref x)   => write!(f, "Operator({})", x),
             Self::String(ref x)     => write!(f, "String({})", x),
-            Self::Integer(ref x)    => write!(f, "Integer({})", x),
-            Self::Float(ref x)      => write!(f, "Float({})", x),
+            Self::Integer { value, .. } => write!(f, "Integer({})", value),
+            Self::Float { value, .. }   => write!(f, "Float({})", value),
             Self::Identifier(ref x) => write!(f, "Identifier({})", x),
             Self::Keyword(ref x)    => write!(f, "Keyword({})", x),
             Self::EndOfStatement    => write!(f, "EndOfStatement(;)"),
@@ -88,73 +101,211 @@ impl fmt::Display for TokenClass {
             Self::CurlyClose        => write!(f, "CurlyClose"),
             Self::BracketOpen       => write!(f, "BracketOpen"),
             Self::BracketClose      => write!(f, "BracketClose"),
-            Self::Namespace         => write!(f, "Namespace(::)")
+            Self::Namespace         => write!(f, "Namespace(::)"),
+            Self::Ellipsis          => write!(f, "Ellipsis(...)"),
+            Self::Question          => write!(f, "Question(?)"),
+            Self::DocComment(ref x) => write!(f, "DocComment({})", x),
         }
     }
 }
 
 
 
+//where a token lives in the source. `offset` and `len` are in bytes,
+//`line` and `col` are 1-based, with columns counted in characters (a tab is one column)
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub len: usize,
+    pub line: u32,
+    pub col: u32,
+}
+
 #[derive(Debug)]
 pub struct Token {
     pub data: TokenClass,
-    pub line_index: u32,
+    pub span: Span,
+}
+
+
+fn is_line_break(char: char) -> bool {
+    char == '\n' || char == '\r'
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub msg: String,
+}
+
+//returned once a syntax error has been recorded in the stream,
+//it tells the parser to stop and resynchronize
+#[derive(Debug)]
+pub struct SyntaxError;
+
+//prints an error along with the offending source line, f.e.:
+//  Error at line 2, column 8: Expected EndOfStatement(;), but got Identifier(c).
+//      2 | a := b c;
+//        |        ^
+pub fn report(source: &str, diagnostic: &Diagnostic) {
+    print_diagnostic("Error", source, diagnostic);
+}
+
+//same as `report`, for problems that don't stop the program from running
+pub fn warn(source: &str, diagnostic: &Diagnostic) {
+    print_diagnostic("Warning", source, diagnostic);
+}
+
+fn print_diagnostic(label: &str, source: &str, diagnostic: &Diagnostic) {
+    let Diagnostic { span, ref msg } = *diagnostic;
+    eprintln!("{} at line {}, column {}: {}", label, span.line, span.col, msg);
+
+    let offset = char_boundary(source, span.offset);
+    let start = source[..offset].rfind(is_line_break).map_or(0, |x| x + 1);
+    let end   = source[offset..].find(is_line_break).map_or(source.len(), |x| x + offset);
+    let line  = &source[start..end];
+
+    //re-use the tabs of the source line, so the marker lines up in any terminal
+    let indent: String = source[start..offset].chars().map(|x| if x == '\t' { '\t' } else { ' ' }).collect();
+    let width = source[offset..char_boundary(source, offset + span.len).min(end)].chars().count().max(1);
+
+    let gutter = span.line.to_string();
+    eprintln!("    {} | {}", gutter, line);
+    eprintln!("    {} | {}{}", " ".repeat(gutter.len()), indent, "^".repeat(width));
+}
+
+//`offset` moved back to the start of the character it is in, and into `source`.
+//spans should never point into a character, but a wrong one shouldn't crash the reporting
+pub fn char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
 }
 
+//reports every error collected in `stream` in source order, returns how many there were
+pub fn report_all(source: &str, stream: &Stream) -> usize {
+    let mut diagnostics = stream.diagnostics().to_vec();
+    diagnostics.sort_by_key(|x| x.span.offset);
+    for diagnostic in &diagnostics {
+        report(source, diagnostic);
+    }
+    diagnostics.len()
+}
+
+
 #[derive(Debug)]
 pub struct Stream {
     tokens: Vec<Token>,
     index: usize,
-    last_line_index: u32,
+    source: Rc<str>,
+    end: Span, //points just past the last character, for errors at the end of input
+    last: Span, //the token that was consumed last
+    diagnostics: Vec<Diagnostic>,
+    comments: Vec<Span>, //every comment, including doc comments and a shebang line
 }
 
 impl Stream {
-    fn push (&mut self, token: Token) {
-        self.tokens.push(token);
-    }
-
     fn has(&self) -> bool {
-        return self.index < self.tokens.len();
-    }
-
-    fn get(&mut self, index: usize) -> &Token {
-        let token = &self.tokens[index];
-        self.last_line_index = token.line_index;
-        token
+        self.index < self.tokens.len()
     }
 
     pub fn peek(&self) -> Option<&Token> {
         if !self.has() { return None }
         
-        return Some(&self.tokens[self.index]);
+        Some(&self.tokens[self.index])
+    }
+
+    //doc comments only matter to tooling, so the parser never gets to see them
+    fn skip_docs(&mut self) {
+        while let Some(Token { data: TokenClass::DocComment(_), .. }) = self.tokens.get(self.index) {
+            self.index += 1;
+        }
     }
 
     pub fn next(&mut self) {
+        if let Some(token) = self.tokens.get(self.index) { self.last = token.span; }
         self.index += 1;
+        self.skip_docs();
     }
 
-    pub fn pop(&mut self) -> Option<&Token> {
-        if !self.has() { return None }
+    //every token, including doc comments, regardless of how far the parser got
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
 
-        let i = self.index;
-        self.next();
-        return Some(self.get(i));
+    pub fn source(&self) -> Rc<str> {
+        self.source.clone()
     }
 
-    pub fn expect(&mut self, should: TokenClass) {
-        let Some(token) = self.pop() else { 
-            self.error(format!("Expected {}, but end of token stream.", should).as_str()) 
+    //span of the token that is up next, or the end of input
+    pub fn span(&self) -> Span {
+        self.peek().map_or(self.end, |x| x.span)
+    }
+
+    //span from `start` up to and including the token that was consumed last
+    pub fn span_from(&self, start: Span) -> Span {
+        let end = (self.last.offset + self.last.len).max(start.offset);
+        Span { len: end - start.offset, ..start }
+    }
+
+    pub fn expect(&mut self, should: TokenClass) -> Result<(), SyntaxError> {
+        let Some(token) = self.peek() else { 
+            return Err(self.error(format!("Expected {}, but end of token stream.", should).as_str()));
         };
-        let got = token.data.clone();
-        if should != got {
-            self.error(format!("Expected {}, but got {}.", should, got).as_str());
+        if should != token.data {
+            let msg = format!("Expected {}, but got {}.", should, token.data);
+            return Err(self.error(msg.as_str()));
         }
+        self.next();
+        Ok(())
+    }
+
+    //errors always point at the token that is up next, since that is the one that didn't fit
+    pub fn error(&mut self, msg: &str) -> SyntaxError {
+        self.error_at(self.span(), msg)
+    }
+
+    pub fn error_at(&mut self, span: Span, msg: &str) -> SyntaxError {
+        self.diagnostics.push(Diagnostic { span, msg: msg.to_string() });
+        SyntaxError
     }
 
-    pub fn error(&self, msg: &str) -> ! {
-        eprintln!("Error at line {}: {}", self.last_line_index, msg);
-        std::process::exit(1);
-        //panic!("EVERYBODY PANIC!!!!")
+    //the parser doesn't see comments, tools like the formatter get them from here
+    pub fn comments(&self) -> &[Span] {
+        &self.comments
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    //takes over the errors of a stream that was lexed from a snippet of this one
+    pub fn absorb(&mut self, other: Stream) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    //skips ahead to where parsing can resume after a syntax error:
+    //past the next `;` or `{ ... }` block (including `elif`/`else` and `catch`/`finally` chains),
+    //or up to the `}` which closes the current block
+    pub fn synchronize(&mut self) {
+        let mut depth: u32 = 0;
+
+        while let Some(token) = self.peek() {
+            match token.data {
+                TokenClass::CurlyOpen => depth += 1,
+                TokenClass::CurlyClose if depth == 0 => return,
+                TokenClass::CurlyClose => {
+                    depth -= 1;
+                    let continues = matches!(self.lookhead(1), Some(TokenClass::Keyword(x)) if ["elif", "else", "catch", "finally"].contains(&x.as_str()));
+                    if depth == 0 && !continues { self.next(); return; }
+                },
+                TokenClass::EndOfStatement if depth == 0 => { self.next(); return; },
+                _ => {},
+            }
+            self.next();
+        }
     }
 
     pub fn maybe(&mut self, can: TokenClass) {
@@ -164,10 +315,11 @@ impl Stream {
     }
 
     pub fn lookhead(&self, offset: usize) -> Option<&TokenClass> {
-        let at: usize = self.index + offset;
-        if at >= self.tokens.len() { return None }
-        
-        return Some(&self.tokens[at].data);
+        self.tokens[self.index.min(self.tokens.len())..]
+            .iter()
+            .map(|x| &x.data)
+            .filter(|x| !matches!(x, TokenClass::DocComment(_)))
+            .nth(offset)
     }
 
 }
@@ -175,135 +327,445 @@ impl Stream {
 
 
 
-//this defines the state for the tokenizer state machine,
-//which detects the boundaries between tokens
-#[derive(PartialEq)]
-enum CharType {
-    Invalid,
-    Alpha,
-    Num,
-    Quote,
-    ParenOpen, ParenClose,      //()
-    CurlyOpen, CurlyClose,      //{}
-    BracketOpen, BracketClose,  //[]
-    Symbol,
-    Format, //newlines, spaces, etc.
+//moves a position past `char`, `next` being the character following it.
+//`\n`, `\r\n` and a lone `\r` all count as a single line break
+pub fn advance(pos: &mut Span, char: char, next: Option<char>) {
+    pos.offset += char.len_utf8();
+
+    let is_break = char == '\n' || (char == '\r' && next != Some('\n'));
+    if is_break {
+        pos.line += 1;
+        pos.col = 1;
+    } else if char != '\r' {
+        pos.col += 1;
+    }
+}
+
+//the scanner walks over the source one character at a time,
+//keeping track of the position it is currently at
+struct Cursor {
+    chars: Vec<char>,
+    positions: Option<Vec<Span>>, //where each character is, if that can't be worked out from the ones before
+    index: usize,
+    pos: Span,
+    diagnostics: Vec<Diagnostic>,
+    comments: Vec<Span>,
+}
+
+impl Cursor {
+    fn peek(&self) -> Option<char> {
+        self.lookhead(0)
+    }
+
+    fn lookhead(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.index + offset).copied()
+    }
+
+    fn starts_with(&self, pattern: &str) -> bool {
+        pattern.chars().enumerate().all(|(i, x)| self.lookhead(i) == Some(x))
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let char = self.peek()?;
+        self.index += 1;
+        let next = self.peek();
+        match &self.positions {
+            Some(x) => self.pos = x[self.index],
+            None => advance(&mut self.pos, char, next),
+        }
+        Some(char)
+    }
+
+    fn skip(&mut self, count: usize) {
+        for _ in 0..count { self.bump(); }
+    }
+
+    fn eat_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let mut out = String::new();
+        while let Some(x) = self.peek() {
+            if !pred(x) { break; }
+            out.push(x);
+            self.bump();
+        }
+        out
+    }
+
+    //span from the given start position up to the current one
+    fn span_from(&self, start: Span) -> Span {
+        Span { len: self.pos.offset - start.offset, ..start }
+    }
+
+    fn error(&mut self, span: Span, msg: &str) {
+        self.diagnostics.push(Diagnostic { span, msg: msg.to_string() });
+    }
+
+    //records the comment that was just skipped
+    fn comment(&mut self, start: Span) {
+        let span = self.span_from(start);
+        self.comments.push(span);
+    }
 }
 
 
-fn get_char_state(char: char) -> CharType {
-    return match char {
-        x if x.is_alphabetic() => CharType::Alpha,
-        '_' => CharType::Alpha,
-        x if x.is_numeric() => CharType::Num,
-        '.' => CharType::Num,
-        '"' => CharType::Quote,
-        '(' => CharType::ParenOpen,   ')' => CharType::ParenClose,
-        '{' => CharType::CurlyOpen,   '}' => CharType::CurlyClose,
-        '[' => CharType::BracketOpen, ']' => CharType::BracketClose,
-        ' ' | '\n' | '\t' => CharType::Format,
-        _ => CharType::Symbol
+fn is_doc_comment(cursor: &Cursor) -> bool {
+    //`////...` is a plain comment, same as in rust
+    cursor.starts_with("///") && !cursor.starts_with("////")
+}
+
+fn lex_doc_comment(cursor: &mut Cursor) -> TokenClass {
+    cursor.skip(3);
+    let text = cursor.eat_while(|x| !is_line_break(x));
+    TokenClass::DocComment(text.strip_prefix(' ').unwrap_or(&text).to_string())
+}
+
+fn skip_line_comment(cursor: &mut Cursor) {
+    cursor.eat_while(|x| !is_line_break(x));
+}
+
+//block comments nest, so `/* a /* b */ c */` is a single comment
+fn skip_block_comment(cursor: &mut Cursor) {
+    let start = cursor.pos;
+    let mut depth: u32 = 0;
+
+    loop {
+        if cursor.starts_with("/*") {
+            cursor.skip(2);
+            depth += 1;
+        } else if cursor.starts_with("*/") {
+            cursor.skip(2);
+            depth -= 1;
+            if depth == 0 { return; }
+        } else if cursor.bump().is_none() {
+            cursor.error(Span { len: 2, ..start }, "Unterminated comment, expected a closing `*/`.");
+            return;
+        }
     }
 }
 
+fn lex_string(cursor: &mut Cursor) -> TokenClass {
+    let start = cursor.pos;
+    cursor.bump(); //opening quote
+
+    let mut content = String::new();
+    loop {
+        match cursor.bump() {
+            Some('"')  => break,
+            Some('\\') => {
+                //an escaped quote doesn't end the string
+                content.push('\\');
+                if let Some(x) = cursor.bump() { content.push(x); }
+            },
+            Some(x)    => content.push(x),
+            None       => { cursor.error(Span { len: 1, ..start }, "Unterminated string literal."); break; },
+        }
+    }
+
+    TokenClass::String(content)
+}
 
+fn lex_word(cursor: &mut Cursor) -> TokenClass {
+    let word = cursor.eat_while(|x| x.is_alphanumeric() || x == '_');
+    match word.as_str() {
+        x if KEYWORDS.contains(&x) => TokenClass::Keyword(word),
+        _                          => TokenClass::Identifier(word),
+    }
+}
 
+//digits of a number literal may be separated by underscores, f.e. `1_000_000`.
+//digits that don't even fit into a u64 give `u64::MAX`, which is reported as too large later on
+fn parse_digits(digits: &str, radix: u32) -> Option<u64> {
+    let digits = digits.replace('_', "");
+    if digits.is_empty() { return None; }
+    match u64::from_str_radix(&digits, radix) {
+        Ok(x) => Some(x),
+        Err(x) if *x.kind() == IntErrorKind::PosOverflow => Some(u64::MAX),
+        Err(_) => None,
+    }
+}
 
+//the value of a number literal, before checking whether oko can represent it
+enum Number {
+    Integer(u64),
+    Float(f64),
+}
 
-fn push_token(out: &mut Stream, state: &CharType, buffer: &String, line_index: u32) {
-    let buf_ref: &str = buffer.as_str();
+fn parse_decimal(number: &str) -> Option<Number> {
+    let is_float = number.contains(['.', 'e', 'E']);
+    if !is_float {
+        return parse_digits(number, 10).map(Number::Integer);
+    }
 
-    let data: TokenClass =  match *state {
-        CharType::Invalid   => { return; },
-        CharType::Format    => { return; },
-        CharType::Alpha     => {
-            let content: String = do_escape_sequences(buffer.clone());
-            match buf_ref {
-                x if KEYWORDS.contains(&x) => TokenClass::Keyword(content),
-                _                          => TokenClass::Identifier(content),
-            }
-        },
-        CharType::Num => match buf_ref {
-            x if x.parse::<u64>().is_ok() => TokenClass::Integer(x.parse().unwrap()),
-            x if x.parse::<f64>().is_ok() => TokenClass::Float  (x.parse().unwrap()),
-            x => panic!("Error: Token '{}' looks like a number, but cannot be parsed.", x)
-        },
-        CharType::Quote     => TokenClass::String(buffer.trim_matches('"').to_string()),
-        CharType::ParenOpen   => TokenClass::ParenOpen,   CharType::ParenClose   => TokenClass::ParenClose,
-        CharType::CurlyOpen   => TokenClass::CurlyOpen,   CharType::CurlyClose   => TokenClass::CurlyClose,
-        CharType::BracketOpen => TokenClass::BracketOpen, CharType::BracketClose => TokenClass::BracketClose,
-        CharType::Symbol    => match buf_ref {
-            "=" => TokenClass::Assign, ":=" => TokenClass::Define, 
-            "+=" | "-=" | "*=" | "/=" => TokenClass::AssignOp(buffer.clone()),
-            "," => TokenClass::Comma,
-            ";" => TokenClass::EndOfStatement,
-            "::" => TokenClass::Namespace,
-            x if OPERATORS.contains(&x) => TokenClass::Operator(buffer.clone()),
-            x => panic!("Error: Symbol '{}' cannot be categorized.", x),
-        },
+    //rust's float parser is a bit too lenient (`1.`, `1.e5`, `.e1`), so check the shape by hand:
+    //a `.` needs digits after it, an exponent needs digits too
+    let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e.strip_prefix(['+', '-']).unwrap_or(e))),
+        None         => (number, None),
+    };
+    let (int_part, frac_part) = match mantissa.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, Some(frac_part)),
+        None                        => (mantissa, None),
     };
 
-    out.push(Token {
-        data,
-        line_index,
-    }); 
+    let is_digits  = |x: &str| x.chars().all(|x| x.is_ascii_digit() || x == '_');
+    let has_digits = |x: &str| x.chars().any(|x| x.is_ascii_digit());
+    let well_formed =
+        is_digits(int_part) &&
+        frac_part.is_none_or(|x| is_digits(x) && has_digits(x)) &&
+        (has_digits(int_part) || frac_part.is_some()) &&
+        exponent.is_none_or(|x| is_digits(x) && has_digits(x));
+    if !well_formed { return None; }
 
+    number.replace('_', "").parse::<f64>().ok().map(Number::Float)
 }
 
-fn should_always_transition(state: &CharType) -> bool {
-    match state {
-        CharType::ParenOpen | CharType::ParenClose |
-        CharType::CurlyOpen | CharType::CurlyClose |
-        CharType::BracketOpen | CharType::BracketClose
-          => true,
-        _ => false
+fn lex_number(cursor: &mut Cursor) -> TokenClass {
+    let start = cursor.pos;
+
+    //take in everything that could belong to the literal, so that f.e. `1.2.3` or `12ab`
+    //is reported as a whole, instead of silently being split into multiple tokens
+    let mut number = String::new();
+    while let Some(x) = cursor.peek() {
+        let is_exponent_sign = (x == '+' || x == '-')
+            && number.ends_with(['e', 'E'])
+            && !number.starts_with("0x") && !number.starts_with("0X");
+        if !(x.is_alphanumeric() || x == '_' || x == '.' || is_exponent_sign) { break; }
+        number.push(x);
+        cursor.bump();
     }
+
+    let data = match number.get(..2) {
+        Some("0x" | "0X") => parse_digits(&number[2..], 16).map(Number::Integer),
+        Some("0b" | "0B") => parse_digits(&number[2..], 2 ).map(Number::Integer),
+        Some("0o" | "0O") => parse_digits(&number[2..], 8 ).map(Number::Integer),
+        _                 => parse_decimal(&number),
+    };
+
+    let span = cursor.span_from(start);
+    let msg = match data {
+        //integer literals are unsigned, but have to fit into oko's signed integers
+        Some(Number::Integer(x)) if x > i64::MAX as u64 =>
+            format!("Number literal '{}' is too large, the maximum is {}.", number, i64::MAX),
+        Some(Number::Float(x)) if !x.is_finite() =>
+            format!("Number literal '{}' is too large to be represented.", number),
+        Some(Number::Integer(value)) => return TokenClass::Integer { value, text: number },
+        Some(Number::Float(value))   => return TokenClass::Float { value, text: number },
+        None    => format!("Malformed number literal '{}'.", number),
+    };
+
+    //carry on with a placeholder, the error has been noted
+    cursor.error(span, msg.as_str());
+    TokenClass::Integer { value: 0, text: number }
 }
 
+fn lex_symbol(cursor: &mut Cursor) -> Option<TokenClass> {
+    let Some(symbol) = SYMBOLS.iter().find(|x| cursor.starts_with(x)) else {
+        let char = cursor.peek().unwrap();
+        let span = Span { len: char.len_utf8(), ..cursor.pos };
+        cursor.error(span, format!("Symbol '{}' cannot be categorized.", char).as_str());
+        cursor.bump();
+        return None;
+    };
+    cursor.skip(symbol.len());
+
+    Some(match *symbol {
+        "=" => TokenClass::Assign, ":=" => TokenClass::Define,
+        "+=" | "-=" | "*=" | "/=" => TokenClass::AssignOp(symbol.to_string()),
+        "," => TokenClass::Comma,
+        ";" => TokenClass::EndOfStatement,
+        "::" => TokenClass::Namespace,
+        "..." => TokenClass::Ellipsis,
+        "?"   => TokenClass::Question,
+        x if OPERATORS.contains(&x) => TokenClass::Operator(symbol.to_string()),
+        _ => unreachable!(),
+    })
+}
 
 
-pub fn lex(source: &String) -> Stream {
-    let mut out = Stream { tokens: vec![], index: 0, last_line_index: 0};
 
-    let mut buffer: String = Default::default();
-    let mut last  = CharType::Invalid;
-    let mut state;
+pub fn lex(source: &str) -> Stream {
+    let start = Span { offset: 0, len: 0, line: 1, col: 1 };
+    let source: Rc<str> = Rc::from(source);
+    let cursor = Cursor { chars: source.chars().collect(), positions: None, index: 0, pos: start, diagnostics: vec![], comments: vec![] };
+    scan(source, cursor)
+}
 
-    let mut line_index: u32 = 1;
+//lexes a snippet of the given source, made of characters along with where they are in it, and where it ends.
+//used for the expressions embedded in interpolated strings, where f.e. `\\"` is a single character
+pub fn lex_snippet(source: Rc<str>, snippet: &[(char, Span)], end: Span) -> Stream {
+    let chars = snippet.iter().map(|x| x.0).collect();
+    let positions: Vec<Span> = snippet.iter().map(|x| x.1).chain([end]).collect();
+    let cursor = Cursor { chars, pos: positions[0], positions: Some(positions), index: 0, diagnostics: vec![], comments: vec![] };
+    scan(source, cursor)
+}
 
-    let mut in_comment: bool = false;
-    let mut in_string : bool = false;
+fn scan(source: Rc<str>, mut cursor: Cursor) -> Stream {
+    let start = cursor.pos;
+    let mut tokens: Vec<Token> = vec![];
+
+    while let Some(char) = cursor.peek() {
+        let start = cursor.pos;
+
+        let data = match char {
+            x if x.is_whitespace() => { cursor.bump(); continue; },
+            //a `#!/usr/bin/env oko` line, so scripts can be run directly. the line break stays, keeping line numbers intact
+            '#' if cursor.pos.offset == 0 && cursor.starts_with("#!") => { skip_line_comment(&mut cursor); cursor.comment(start); continue; },
+            '/' if is_doc_comment(&cursor)     => { let x = lex_doc_comment(&mut cursor); cursor.comment(start); x },
+            '/' if cursor.starts_with("//") => { skip_line_comment(&mut cursor);  cursor.comment(start); continue; },
+            '/' if cursor.starts_with("/*") => { skip_block_comment(&mut cursor); cursor.comment(start); continue; },
+            '"' => lex_string(&mut cursor),
+            x if x.is_alphabetic() || x == '_' => lex_word(&mut cursor),
+            x if x.is_ascii_digit()            => lex_number(&mut cursor),
+            '.' if cursor.lookhead(1).is_some_and(|x| x.is_ascii_digit()) => lex_number(&mut cursor),
+            '(' => { cursor.bump(); TokenClass::ParenOpen   }, ')' => { cursor.bump(); TokenClass::ParenClose   },
+            '{' => { cursor.bump(); TokenClass::CurlyOpen   }, '}' => { cursor.bump(); TokenClass::CurlyClose   },
+            '[' => { cursor.bump(); TokenClass::BracketOpen }, ']' => { cursor.bump(); TokenClass::BracketClose },
+            _ => match lex_symbol(&mut cursor) { Some(x) => x, None => continue },
+        };
 
-    for char in source.chars() {
-        state = get_char_state(char);
+        tokens.push(Token { data, span: cursor.span_from(start) });
+    }
 
-        if buffer == "//" { in_comment = true; buffer.clear() }
-        if last == CharType::Quote { in_string = !in_string; }
+    let mut out = Stream {
+        tokens, index: 0, source, end: cursor.pos, last: start,
+        diagnostics: cursor.diagnostics, comments: cursor.comments,
+    };
+    out.skip_docs();
+    out
+}
 
-        //"transite" = (Lat.) "go over!" (imperative of "transire", "to transition")
-        let transite = (state != last) || should_always_transition(&last);
-        if transite && !in_comment && !in_string {
-            push_token(&mut out, &last, &buffer, line_index);
-            buffer.clear();
-        }
 
-        if char   == '\n' { in_comment = false; line_index += 1; }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if !in_comment {
-            buffer.push(char);
-        }
-        last = state;
+    fn classes(source: &str) -> Vec<TokenClass> {
+        lex(source).tokens.iter().map(|x| x.data.clone()).collect()
     }
 
-    return out;
-}
+    fn ident(name: &str) -> TokenClass {
+        TokenClass::Identifier(name.to_string())
+    }
 
+    #[test]
+    fn symbols_take_the_longest_match() {
+        assert_eq!(classes("a := b += c;"), vec![
+            ident("a"), TokenClass::Define, ident("b"), TokenClass::AssignOp("+=".to_string()),
+            ident("c"), TokenClass::EndOfStatement,
+        ]);
+        assert_eq!(classes("a<=b"), vec![ident("a"), TokenClass::Operator("<=".to_string()), ident("b")]);
+    }
 
+    #[test]
+    fn keywords_and_identifiers() {
+        assert_eq!(classes("fun funny _x"), vec![TokenClass::Keyword("fun".to_string()), ident("funny"), ident("_x")]);
+    }
 
+    fn spans(source: &str) -> Vec<(usize, usize, u32, u32)> {
+        lex(source).tokens.iter().map(|x| (x.span.offset, x.span.len, x.span.line, x.span.col)).collect()
+    }
 
+    #[test]
+    fn strings_are_kept_as_written() {
+        assert_eq!(classes("\"a\\\"é\\n\" x"), vec![TokenClass::String("a\\\"é\\n".to_string()), ident("x")]);
+        assert_eq!(spans("\"a\\\"é\\n\" x"), vec![(0, 9, 1, 1), (10, 1, 1, 10)]);
+    }
 
+    #[test]
+    fn char_boundaries() {
+        assert_eq!(char_boundary("aé", 2), 1);
+        assert_eq!(char_boundary("aé", 3), 3);
+        assert_eq!(char_boundary("aé", 10), 3);
+    }
 
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(classes("a // line\n/* block /* nested */ still */ b"), vec![ident("a"), ident("b")]);
+        assert_eq!(classes("a/**/b"), vec![ident("a"), ident("b")]);
+    }
 
+    #[test]
+    fn doc_comments_are_hidden_from_the_parser() {
+        let stream = lex("/// docs\n//// plain\nfun");
+        assert_eq!(stream.tokens.len(), 2);
+        assert_eq!(stream.tokens[0].data, TokenClass::DocComment("docs".to_string()));
+        assert_eq!(stream.peek().map(|x| &x.data), Some(&TokenClass::Keyword("fun".to_string())));
+    }
 
+    #[test]
+    fn shebang_only_on_the_first_line() {
+        assert_eq!(classes("#!/usr/bin/env oko\na"), vec![ident("a")]);
+        assert_eq!(lex("a\n#!b").diagnostics().len(), 1);
+    }
 
+    #[derive(Debug, PartialEq)]
+    enum Number {
+        Integer(u64),
+        Float(f64),
+    }
 
+    fn number(source: &str) -> Result<Number, String> {
+        let stream = lex(source);
+        if let [x, ..] = stream.diagnostics() { return Err(x.msg.clone()) }
+        match &stream.tokens[0].data {
+            TokenClass::Integer { value, text } if text == source => Ok(Number::Integer(*value)),
+            TokenClass::Float { value, text } if text == source => Ok(Number::Float(*value)),
+            x => panic!("Not a number literal spelled '{}': {:?}", source, x),
+        }
+    }
+
+    #[test]
+    fn number_literals() {
+        assert_eq!(number("1_000"), Ok(Number::Integer(1000)));
+        assert_eq!(number("0xFF"),  Ok(Number::Integer(255)));
+        assert_eq!(number("0b101"), Ok(Number::Integer(5)));
+        assert_eq!(number("0o17"),  Ok(Number::Integer(15)));
+        assert_eq!(number("1.5"),   Ok(Number::Float(1.5)));
+        assert_eq!(number(".5"),    Ok(Number::Float(0.5)));
+        assert_eq!(number("1e3"),   Ok(Number::Float(1000.0)));
+        assert_eq!(number("2.5E-1"), Ok(Number::Float(0.25)));
+        assert_eq!(number("9223372036854775807"), Ok(Number::Integer(i64::MAX as u64)));
+    }
+
+    #[test]
+    fn malformed_number_literals() {
+        for source in ["0x", "0b", "0b102", "0xG", "1.", "1.e5", "1e", "1e+", "1.2.3", "12ab", "1_e5x"] {
+            assert_eq!(number(source), Err(format!("Malformed number literal '{}'.", source)), "{}", source);
+        }
+    }
+
+    #[test]
+    fn number_literals_that_are_too_large() {
+        let too_large = |x: &str| Err(format!("Number literal '{}' is too large, the maximum is {}.", x, i64::MAX));
+        let binary = format!("0b1{}", "0".repeat(64));
+        for source in ["9223372036854775808", "99999999999999999999999", "0xFFFFFFFFFFFFFFFFF", &binary] {
+            assert_eq!(number(source), too_large(source), "{}", source);
+        }
+        assert_eq!(number("1e999"), Err("Number literal '1e999' is too large to be represented.".to_string()));
+    }
+
+    #[test]
+    fn errors_are_collected() {
+        let stream = lex("a @ b \"c");
+        let msgs: Vec<&str> = stream.diagnostics().iter().map(|x| x.msg.as_str()).collect();
+        assert_eq!(msgs, vec!["Symbol '@' cannot be categorized.", "Unterminated string literal."]);
+        assert_eq!(classes("a @ b"), vec![ident("a"), ident("b")]);
+    }
+
+    #[test]
+    fn line_numbers() {
+        let lines: Vec<u32> = spans("a\nb /* c\n */\n\nd").iter().map(|x| x.2).collect();
+        assert_eq!(lines, vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn spans_count_bytes_and_characters() {
+        //`é` is two bytes, but a single column
+        assert_eq!(spans("é := 12;"), vec![(0, 2, 1, 1), (3, 2, 1, 3), (6, 2, 1, 6), (8, 1, 1, 8)]);
+        assert_eq!(spans("\ta"), vec![(1, 1, 1, 2)]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(spans("a\r\nb\rc\nd").iter().map(|x| (x.2, x.3)).collect::<Vec<_>>(), vec![(1, 1), (2, 1), (3, 1), (4, 1)]);
+    }
+}
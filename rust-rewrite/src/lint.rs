@@ -0,0 +1,463 @@
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::ast::{self, AssignOp, ElseBlock, Expr, ExprKind, FunctionDeclare, IfStat, StatSeq, Stmt, StmtKind, Visitor};
+use crate::lexer::{Diagnostic, Span};
+
+
+//the warnings of `oko check`. unlike the checker's errors they point at code that runs,
+//but most likely doesn't do what was intended.
+//every rule can be turned off, either for a whole project in a `.okolint` file:
+//  # one rule per line
+//  unused-parameter = off
+//or in the program itself with comments:
+//  // lint: allow unused-variable          on this and the next line
+//  // lint: disable constant-condition     from here on, until a matching `// lint: enable ...`
+//names starting with `_` never count as unused.
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rule {
+    UnusedVariable,
+    UnusedParameter,
+    ShadowedVariable,
+    UnreachableCode,
+    ConstantCondition,
+    UnusedImport,
+    SelfAssignment,
+}
+
+const RULES: &[(Rule, &str)] = &[
+    (Rule::UnusedVariable,    "unused-variable"),
+    (Rule::UnusedParameter,   "unused-parameter"),
+    (Rule::ShadowedVariable,  "shadowed-variable"),
+    (Rule::UnreachableCode,   "unreachable-code"),
+    (Rule::ConstantCondition, "constant-condition"),
+    (Rule::UnusedImport,      "unused-import"),
+    (Rule::SelfAssignment,    "self-assignment"),
+];
+
+impl Rule {
+    fn name(self) -> &'static str {
+        RULES.iter().find(|x| x.0 == self).unwrap().1
+    }
+
+    fn by_name(name: &str) -> Option<Rule> {
+        RULES.iter().find(|x| x.1 == name).map(|x| x.0)
+    }
+}
+
+
+//which rules are turned on, all of them unless a `.okolint` file says otherwise
+#[derive(Default)]
+pub struct Config {
+    disabled: Vec<Rule>,
+}
+
+impl Config {
+    //the `.okolint` file in `dir`, if there is one
+    pub fn load(dir: &Path) -> Result<Config, String> {
+        let path = dir.join(".okolint");
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Ok(Config::default());
+        };
+        Config::parse(&text).map_err(|x| format!("{}: {}", path.display(), x))
+    }
+
+    fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() { continue }
+
+            let Some((name, value)) = line.split_once('=') else {
+                return Err(format!("Expected `<rule> = on|off` on line {}, but got '{}'.", index + 1, line));
+            };
+            let Some(rule) = Rule::by_name(name.trim()) else {
+                return Err(format!("Unknown lint rule '{}' on line {}.", name.trim(), index + 1));
+            };
+            config.disabled.retain(|x| *x != rule);
+            match value.trim() {
+                "on"  => {},
+                "off" => config.disabled.push(rule),
+                x => return Err(format!("Expected `on` or `off` for rule {} on line {}, but got '{}'.", rule.name(), index + 1, x)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+
+//a `// lint: ...` comment
+enum Directive {
+    Allow(Vec<Rule>),
+    Disable(Vec<Rule>),
+    Enable(Vec<Rule>),
+}
+
+//the directives of a program by line, unknown rules in them are reported as warnings of their own
+fn directives(source: &str, comments: &[Span], warnings: &mut Vec<Diagnostic>) -> Vec<(u32, Directive)> {
+    let mut directives = vec![];
+    for span in comments {
+        let text = &source[span.offset..span.offset + span.len];
+        let text = text.trim_start_matches(['/', '*', '!']).trim_end().trim_end_matches("*/").trim();
+        let Some(text) = text.strip_prefix("lint:") else { continue };
+
+        let mut words = text.split([' ', ',']).filter(|x| !x.is_empty());
+        let kind = words.next().unwrap_or("");
+        let mut rules = vec![];
+        for name in words {
+            match Rule::by_name(name) {
+                Some(rule) => rules.push(rule),
+                None => warnings.push(Diagnostic { span: *span, msg: format!("Unknown lint rule {}.", name) }),
+            }
+        }
+        directives.push((span.line, match kind {
+            "allow"   => Directive::Allow(rules),
+            "disable" => Directive::Disable(rules),
+            "enable"  => Directive::Enable(rules),
+            x => {
+                warnings.push(Diagnostic { span: *span, msg: format!("Expected allow, disable or enable after `lint:`, but got '{}'.", x) });
+                continue;
+            },
+        }));
+    }
+    directives
+}
+
+fn is_enabled(rule: Rule, line: u32, config: &Config, directives: &[(u32, Directive)]) -> bool {
+    let mut enabled = !config.disabled.contains(&rule);
+    for (at, directive) in directives {
+        match directive {
+            Directive::Allow(x)   if x.contains(&rule) && (*at == line || *at + 1 == line) => return false,
+            Directive::Disable(x) if x.contains(&rule) && *at <= line => enabled = false,
+            Directive::Enable(x)  if x.contains(&rule) && *at <= line => enabled = true,
+            _ => {},
+        }
+    }
+    enabled
+}
+
+
+//a variable or parameter, and whether anything reads it
+struct Binding {
+    name: String,
+    span: Span,
+    rule: Rule, //what it is reported as if it's never read
+    used: bool,
+}
+
+struct Scope {
+    function: bool, //a function or the program, as opposed to the body of a `for`
+    bindings: Vec<Binding>,
+    late_reads: HashSet<String>, //read by nested functions before being defined here
+}
+
+struct Linter {
+    scopes: Vec<Scope>,
+    imports: Vec<(String, Span)>,
+    accessed: HashSet<String>, //modules used anywhere in the program
+    warnings: Vec<(Rule, Diagnostic)>,
+}
+
+//whether an expression always has the same value, it only uses literals
+fn is_constant(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Int { .. } | ExprKind::Float { .. } | ExprKind::Str(_) => true,
+        ExprKind::Format(x) | ExprKind::Array(x) => x.iter().all(is_constant),
+        ExprKind::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        ExprKind::Unary { operand, .. } | ExprKind::Propagate(operand) => is_constant(operand),
+        ExprKind::Variable(_) | ExprKind::Call(_) | ExprKind::ModAccess { .. } | ExprKind::Error => false,
+    }
+}
+
+//whether running a statement always ends in a `return`
+fn always_returns(stmt: &Stmt) -> bool {
+    match &stmt.kind {
+        StmtKind::Return { .. } => true,
+        StmtKind::If(stat) => if_returns(stat),
+        _ => false,
+    }
+}
+
+fn if_returns(stat: &IfStat) -> bool {
+    let if_block = stat.if_block.stmts.iter().any(always_returns);
+    match &stat.else_block {
+        Some(ElseBlock::Elif(x)) => if_block && if_returns(x),
+        Some(ElseBlock::Else(x)) => if_block && x.stmts.iter().any(always_returns),
+        None => false,
+    }
+}
+
+impl Linter {
+    fn warn(&mut self, rule: Rule, span: Span, msg: String) {
+        self.warnings.push((rule, Diagnostic { span, msg }));
+    }
+
+    fn push_scope(&mut self, function: bool) {
+        self.scopes.push(Scope { function, bindings: vec![], late_reads: HashSet::new() });
+    }
+
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for binding in scope.bindings {
+            if binding.used || binding.name.starts_with('_') { continue }
+            let what = if binding.rule == Rule::UnusedParameter { "Parameter" } else { "Variable" };
+            self.warn(binding.rule, binding.span, format!("{} {} is never used.", what, binding.name));
+        }
+    }
+
+    fn bind(&mut self, name: &str, span: Span, rule: Rule) {
+        let scope = self.scopes.last_mut().unwrap();
+        let used = scope.late_reads.contains(name);
+        scope.bindings.push(Binding { name: name.to_string(), span, rule, used });
+    }
+
+    //marks the latest definition of `name` as used. outside of the function doing the reading,
+    //a later definition counts too, since the function only runs once it's been called
+    fn read(&mut self, name: &str) {
+        let mut nested = false;
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.bindings.iter_mut().rev().find(|x| x.name == name) {
+                binding.used = true;
+                return;
+            }
+            if nested { scope.late_reads.insert(name.to_string()); }
+            if scope.function { nested = true; }
+        }
+    }
+
+    fn fun(&mut self, decl: &FunctionDeclare) {
+        self.push_scope(true);
+        for param in decl.args.iter().chain(&decl.rest) {
+            if let Some(default) = &param.default {
+                self.visit_expr(default);
+            }
+            self.bind(&param.name, param.span, Rule::UnusedParameter);
+        }
+        self.visit_block(&decl.body);
+        self.pop_scope();
+    }
+
+    fn condition(&mut self, expr: &Expr) {
+        if is_constant(expr) {
+            self.warn(Rule::ConstantCondition, expr.span, "Condition is constant, it only uses literals.".to_string());
+        }
+    }
+}
+
+impl Visitor for Linter {
+    fn visit_block(&mut self, block: &StatSeq) {
+        let mut returned = false;
+        let mut warned = false; //once per block is enough
+        for stmt in &block.stmts {
+            if returned && !warned {
+                self.warn(Rule::UnreachableCode, stmt.span, "Unreachable code, the statements before it always return.".to_string());
+                warned = true;
+            }
+            self.visit_stmt(stmt);
+            returned |= always_returns(stmt);
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Import { mod_name } => self.imports.push((mod_name.clone(), stmt.span)),
+            StmtKind::Assign { var_name, op, expr } => {
+                self.visit_expr(expr);
+                let name_span = Span { len: var_name.len(), ..stmt.span };
+                match op {
+                    AssignOp::Define => {
+                        let (current, outer) = self.scopes.split_last().unwrap();
+                        let shadows = outer.iter().any(|x| x.bindings.iter().any(|x| &x.name == var_name));
+                        if shadows && !current.bindings.iter().any(|x| &x.name == var_name) {
+                            self.warn(Rule::ShadowedVariable, name_span, format!("Variable {} shadows one of an outer scope.", var_name));
+                        }
+                        self.bind(var_name, name_span, Rule::UnusedVariable);
+                    },
+                    AssignOp::Assign => {
+                        if matches!(&expr.kind, ExprKind::Variable(x) if x == var_name) {
+                            self.warn(Rule::SelfAssignment, stmt.span, format!("Variable {} is assigned to itself.", var_name));
+                        }
+                    },
+                    _ => {},
+                }
+            },
+            StmtKind::Fun(decl) => self.fun(decl),
+            StmtKind::If(stat) => {
+                let mut next = Some(stat);
+                while let Some(stat) = next {
+                    self.condition(&stat.condition);
+                    self.visit_expr(&stat.condition);
+                    self.visit_block(&stat.if_block);
+                    next = match &stat.else_block {
+                        Some(ElseBlock::Elif(x)) => Some(x),
+                        Some(ElseBlock::Else(x)) => { self.visit_block(x); None },
+                        None => None,
+                    };
+                }
+            },
+            StmtKind::While { condition, .. } => {
+                self.condition(condition);
+                ast::walk_stmt(self, stmt);
+            },
+            StmtKind::For { elem_name, elem_span, array, body } => {
+                self.visit_expr(array);
+                self.push_scope(false);
+                self.bind(elem_name, *elem_span, Rule::UnusedVariable);
+                self.visit_block(body);
+                self.pop_scope();
+            },
+            StmtKind::Try { body, catch, finally } => {
+                self.visit_block(body);
+                if let Some(catch) = catch {
+                    self.push_scope(false);
+                    self.bind(&catch.name, catch.span, Rule::UnusedVariable);
+                    self.visit_block(&catch.body);
+                    self.pop_scope();
+                }
+                if let Some(x) = finally { self.visit_block(x) }
+            },
+            _ => ast::walk_stmt(self, stmt),
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Variable(name) => self.read(name),
+            ExprKind::ModAccess { mod_name, .. } => { self.accessed.insert(mod_name.clone()); },
+            _ => {},
+        }
+        ast::walk_expr(self, expr);
+    }
+}
+
+
+//the warnings for a program without syntax errors, in source order
+pub fn lint(source: &str, comments: &[Span], root: &StatSeq, config: &Config) -> Vec<Diagnostic> {
+    let mut linter = Linter { scopes: vec![], imports: vec![], accessed: HashSet::new(), warnings: vec![] };
+    linter.push_scope(true);
+    linter.visit_block(root);
+    linter.pop_scope();
+    for (name, span) in std::mem::take(&mut linter.imports) {
+        if !linter.accessed.contains(&name) {
+            linter.warn(Rule::UnusedImport, span, format!("Module {} is imported, but never used.", name));
+        }
+    }
+
+    let mut warnings = vec![];
+    let directives = directives(source, comments, &mut warnings);
+    for (rule, diagnostic) in linter.warnings {
+        if !is_enabled(rule, diagnostic.span.line, config, &directives) { continue }
+        let msg = format!("{} ({})", diagnostic.msg, rule.name());
+        warnings.push(Diagnostic { msg, ..diagnostic });
+    }
+    warnings.sort_by_key(|x| x.span.offset);
+    warnings
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer, parser};
+
+    fn warnings_with(source: &str, config: &Config) -> Vec<(u32, u32, String)> {
+        let mut stream = lexer::lex(source);
+        let root = parser::parse_program(&mut stream);
+        assert!(stream.diagnostics().is_empty(), "{:?}", stream.diagnostics());
+        lint(source, stream.comments(), &root, config).into_iter().map(|x| (x.span.line, x.span.col, x.msg)).collect()
+    }
+
+    fn warnings(source: &str) -> Vec<(u32, u32, String)> {
+        warnings_with(source, &Config::default())
+    }
+
+    fn warning(line: u32, col: u32, msg: &str) -> (u32, u32, String) {
+        (line, col, msg.to_string())
+    }
+
+    #[test]
+    fn unused_variables_and_parameters() {
+        let source = "fun f(a, b, _c) {\n    x := a;\n    for (e) ([1]) {}\n    try {} catch (err) {}\n}\nf(1, 2, 3);";
+        assert_eq!(warnings(source), vec![
+            warning(1, 10, "Parameter b is never used. (unused-parameter)"),
+            warning(2, 5, "Variable x is never used. (unused-variable)"),
+            warning(3, 10, "Variable e is never used. (unused-variable)"),
+            warning(4, 19, "Variable err is never used. (unused-variable)"),
+        ]);
+
+        //read by a function that only runs after the definition
+        assert!(warnings("fun f() { return x; }\nx := 1;\nf();").is_empty());
+    }
+
+    #[test]
+    fn shadowed_variables() {
+        assert_eq!(warnings("x := 1;\nfun f() { x := 2; return x; }\nf(x);"), vec![
+            warning(2, 11, "Variable x shadows one of an outer scope. (shadowed-variable)"),
+        ]);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let source = "fun f(a) {\n    if (a) { return 1; } else { return 2; }\n    a = 3;\n    a = 4;\n}\nf(1);";
+        assert_eq!(warnings(source), vec![
+            warning(3, 5, "Unreachable code, the statements before it always return. (unreachable-code)"),
+        ]);
+        assert!(warnings("fun f(a) {\n    if (a) { return 1; }\n    return 2;\n}\nf(1);").is_empty());
+    }
+
+    #[test]
+    fn constant_conditions() {
+        assert_eq!(warnings("a := 1;\nif (1 + 2) {} elif (a) {}\nwhile (\"x\") {}"), vec![
+            warning(2, 5, "Condition is constant, it only uses literals. (constant-condition)"),
+            warning(3, 8, "Condition is constant, it only uses literals. (constant-condition)"),
+        ]);
+    }
+
+    #[test]
+    fn unused_imports_and_self_assignments() {
+        assert_eq!(warnings("import io;\nimport tu;\na := tu::getNil();\na = a;"), vec![
+            warning(1, 1, "Module io is imported, but never used. (unused-import)"),
+            warning(4, 1, "Variable a is assigned to itself. (self-assignment)"),
+        ]);
+    }
+
+    #[test]
+    fn config_files() {
+        let config = Config::parse("# comment\nunused-import = off\n\nself-assignment = off # not now\nself-assignment = on").ok().unwrap();
+        assert_eq!(warnings_with("import io;\na := 1;\na = a;", &config), vec![
+            warning(3, 1, "Variable a is assigned to itself. (self-assignment)"),
+        ]);
+
+        assert_eq!(Config::parse("unused-import").err().unwrap(), "Expected `<rule> = on|off` on line 1, but got 'unused-import'.");
+        assert_eq!(Config::parse("\nnope = off").err().unwrap(), "Unknown lint rule 'nope' on line 2.");
+        assert_eq!(Config::parse("unused-import = no").err().unwrap(), "Expected `on` or `off` for rule unused-import on line 1, but got 'no'.");
+    }
+
+    #[test]
+    fn inline_directives() {
+        let source = "\
+// lint: allow unused-variable
+a := 1;
+b := 2;
+// lint: disable unused-variable, self-assignment
+c := 3;
+s := 0;
+s = s;
+/* lint: enable unused-variable */
+d := 4;
+s = s;";
+        assert_eq!(warnings(source), vec![
+            warning(3, 1, "Variable b is never used. (unused-variable)"),
+            warning(9, 1, "Variable d is never used. (unused-variable)"),
+        ]);
+
+        assert_eq!(warnings("// lint: allow nope\n// lint: forbid unused-variable\n_a := 1;"), vec![
+            warning(1, 1, "Unknown lint rule nope."),
+            warning(2, 1, "Expected allow, disable or enable after `lint:`, but got 'forbid'."),
+        ]);
+    }
+}
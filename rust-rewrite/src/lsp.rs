@@ -0,0 +1,543 @@
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::ast::{self, ElseBlock, FunctionDeclare, StatSeq, Stmt, StmtKind, Visitor};
+use crate::builtins;
+use crate::checker::{self, Definition};
+use crate::formatter;
+use crate::json::{self, Json};
+use crate::lexer::{self, Diagnostic, Span, Stream, TokenClass};
+use crate::lint;
+use crate::parser;
+
+
+//`oko lsp`, a language server for editors, speaking json-rpc over stdin and stdout.
+//every request lexes and parses the document again, oko programs are small enough for that.
+//it provides:
+// - diagnostics: syntax errors, or the errors and warnings of `oko check`
+// - go to definition of functions and variables
+// - hover with the signature and doc comments of functions and builtins
+// - completion of builtin module members after `::`
+// - document symbols and formatting
+
+
+//error codes of json-rpc and the protocol
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+//kinds of the protocol, as numbers
+const SEVERITY_ERROR: f64 = 1.0;
+const SEVERITY_WARNING: f64 = 2.0;
+const SYMBOL_FUNCTION: f64 = 12.0;
+const SYMBOL_VARIABLE: f64 = 13.0;
+const COMPLETION_FUNCTION: f64 = 3.0;
+const SYNC_FULL: f64 = 1.0; //every change sends the whole document
+
+type Response = Result<Json, (i32, String)>;
+
+
+//a message is a `Content-Length` header, an empty line and that many bytes of json.
+//`None` once the editor closed the connection
+fn read_message(input: &mut impl BufRead) -> Option<Result<Json, String>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 { return None }
+        let line = line.trim_end();
+        if line.is_empty() { break }
+        if let Some(x) = line.strip_prefix("Content-Length:") {
+            len = x.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(len) = len else {
+        return Some(Err("Expected a Content-Length header.".to_string()));
+    };
+    let mut body = vec![0; len];
+    input.read_exact(&mut body).ok()?;
+    Some(json::parse(&String::from_utf8_lossy(&body)))
+}
+
+fn send(output: &mut dyn Write, message: Json) {
+    let body = message.to_string();
+    _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    _ = output.flush();
+}
+
+fn notify(output: &mut dyn Write, method: &str, params: Json) {
+    send(output, Json::object(vec![("jsonrpc", Json::str("2.0")), ("method", Json::str(method)), ("params", params)]));
+}
+
+fn respond(output: &mut dyn Write, id: Json, response: Response) {
+    let outcome = match response {
+        Ok(result) => ("result", result),
+        Err((code, msg)) => ("error", Json::object(vec![("code", Json::Number(code as f64)), ("message", Json::String(msg))])),
+    };
+    send(output, Json::object(vec![("jsonrpc", Json::str("2.0")), ("id", id), outcome]));
+}
+
+
+//positions count lines from 0 and characters in utf-16 code units, spans count bytes.
+//lines end the way the lexer sees it, at `\n`, `\r\n` or a lone `\r`
+
+//the offset every line starts at
+fn line_starts(source: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut pos = Span { line: 1, col: 1, ..Span::default() };
+    let mut chars = source.chars().peekable();
+    while let Some(char) = chars.next() {
+        let line = pos.line;
+        lexer::advance(&mut pos, char, chars.peek().copied());
+        if pos.line != line { starts.push(pos.offset) }
+    }
+    starts
+}
+
+fn position(source: &str, offset: usize) -> Json {
+    let offset = lexer::char_boundary(source, offset);
+    let starts = line_starts(source);
+    let line = starts.partition_point(|x| *x <= offset) - 1;
+    let character: usize = source[starts[line]..offset].chars().map(char::len_utf16).sum();
+    Json::object(vec![("line", Json::Number(line as f64)), ("character", Json::Number(character as f64))])
+}
+
+fn range(source: &str, span: Span) -> Json {
+    let end = (span.offset + span.len).min(source.len());
+    Json::object(vec![("start", position(source, span.offset.min(end))), ("end", position(source, end))])
+}
+
+fn offset_at(source: &str, position: &Json) -> Option<usize> {
+    let line = position.get("line")?.as_number()? as usize;
+    let character = position.get("character")?.as_number()? as usize;
+
+    let offset = *line_starts(source).get(line)?;
+    let mut units = 0;
+    for (index, char) in source[offset..].char_indices() {
+        if units >= character || char == '\n' || char == '\r' { return Some(offset + index) }
+        units += char.len_utf16();
+    }
+    Some(source.len())
+}
+
+//`file:///home/a%20b/x.oko` to `/home/a b/x.oko`
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = vec![];
+    let mut index = 0;
+    while index < encoded.len() {
+        let hex = encoded.get(index + 1..index + 3).and_then(|x| u8::from_str_radix(std::str::from_utf8(x).ok()?, 16).ok());
+        match (encoded[index], hex) {
+            (b'%', Some(x)) => { bytes.push(x); index += 3; },
+            (x, _) => { bytes.push(x); index += 1; },
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+
+//a document, lexed and parsed
+struct Parsed<'a> {
+    source: &'a str,
+    stream: Stream,
+    root: StatSeq,
+}
+
+fn parse(source: &str) -> Parsed<'_> {
+    let mut stream = lexer::lex(source);
+    let root = parser::parse_program(&mut stream);
+    Parsed { source, stream, root }
+}
+
+impl Parsed<'_> {
+    fn diagnostics(&self, uri: &str) -> Json {
+        let diagnostic = |x: &Diagnostic, severity: f64| Json::object(vec![
+            ("range", range(self.source, x.span)),
+            ("severity", Json::Number(severity)),
+            ("source", Json::str("oko")),
+            ("message", Json::String(x.msg.clone())),
+        ]);
+
+        //the rest only makes sense for a complete program
+        if !self.stream.diagnostics().is_empty() {
+            return Json::Array(self.stream.diagnostics().iter().map(|x| diagnostic(x, SEVERITY_ERROR)).collect());
+        }
+
+        let dir = uri_path(uri).and_then(|x| Some(x.parent()?.to_path_buf())).unwrap_or_default();
+        let config = lint::Config::load(&dir).unwrap_or_default();
+        let errors = checker::check(&self.root).diagnostics;
+        let warnings = lint::lint(self.source, self.stream.comments(), &self.root, &config);
+
+        let mut out: Vec<Json> = errors.iter().map(|x| diagnostic(x, SEVERITY_ERROR)).collect();
+        out.extend(warnings.iter().map(|x| diagnostic(x, SEVERITY_WARNING)));
+        Json::Array(out)
+    }
+
+    fn token_at(&self, offset: usize) -> Option<usize> {
+        self.stream.tokens().iter().position(|x| x.span.offset <= offset && offset <= x.span.offset + x.span.len)
+    }
+
+    //the `/// ...` lines right above a statement
+    fn doc_comments(&self, stmt: Span) -> String {
+        let mut lines = vec![];
+        let mut line = stmt.line;
+        for token in self.stream.tokens().iter().rev().skip_while(|x| x.span.offset >= stmt.offset) {
+            let TokenClass::DocComment(ref text) = token.data else { break };
+            if token.span.line + 1 != line { break }
+            lines.push(text.as_str());
+            line = token.span.line;
+        }
+        lines.reverse();
+        lines.join("\n")
+    }
+
+    fn symbols(&self, block: &StatSeq) -> Vec<Json> {
+        let mut out = vec![];
+        for stmt in &block.stmts {
+            match &stmt.kind {
+                StmtKind::Fun(decl) => out.push(self.symbol(&decl.name, SYMBOL_FUNCTION, stmt.span, Some(decl))),
+                StmtKind::Assign { var_name, op: ast::AssignOp::Define, .. } => {
+                    let span = Span { len: var_name.len(), ..stmt.span };
+                    out.push(self.symbol(var_name, SYMBOL_VARIABLE, span, None));
+                },
+                //what is defined in blocks is listed along with the function around them
+                StmtKind::While { body, .. } | StmtKind::For { body, .. } => out.extend(self.symbols(body)),
+                StmtKind::Try { body, catch, finally } => {
+                    out.extend(self.symbols(body));
+                    if let Some(x) = catch { out.extend(self.symbols(&x.body)) }
+                    if let Some(x) = finally { out.extend(self.symbols(x)) }
+                },
+                StmtKind::If(stat) => {
+                    let mut next = Some(stat);
+                    while let Some(stat) = next {
+                        out.extend(self.symbols(&stat.if_block));
+                        next = match &stat.else_block {
+                            Some(ElseBlock::Elif(x)) => Some(x),
+                            Some(ElseBlock::Else(x)) => { out.extend(self.symbols(x)); None },
+                            None => None,
+                        };
+                    }
+                },
+                _ => {},
+            }
+        }
+        out
+    }
+
+    fn symbol(&self, name: &str, kind: f64, span: Span, decl: Option<&Rc<FunctionDeclare>>) -> Json {
+        let mut fields = vec![
+            ("name", Json::str(name)),
+            ("kind", Json::Number(kind)),
+            ("range", range(self.source, span)),
+            ("selectionRange", range(self.source, span)),
+        ];
+        if let Some(decl) = decl {
+            fields.push(("detail", Json::String(formatter::signature(self.source, decl))));
+            fields.push(("children", Json::Array(self.symbols(&decl.body))));
+        }
+        Json::object(fields)
+    }
+}
+
+//the function declared by the `fun` statement starting at `offset`
+struct FunAt {
+    offset: usize,
+    found: Option<(Span, Rc<FunctionDeclare>)>,
+}
+
+impl Visitor for FunAt {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if let StmtKind::Fun(decl) = &stmt.kind {
+            if stmt.span.offset == self.offset { self.found = Some((stmt.span, decl.clone())); }
+        }
+        ast::walk_stmt(self, stmt);
+    }
+}
+
+fn builtin_signature(mod_name: &str, member: &builtins::Member) -> String {
+    format!("{}::{}({})", mod_name, member.name, member.params)
+}
+
+fn hover_text(signature: &str, doc: &str) -> Json {
+    let mut text = format!("```oko\n{}\n```", signature);
+    if !doc.is_empty() {
+        text.push_str(&format!("\n\n{}", doc));
+    }
+    Json::object(vec![("contents", Json::object(vec![("kind", Json::str("markdown")), ("value", Json::String(text))]))])
+}
+
+
+struct Server<'a> {
+    documents: HashMap<String, String>, //by uri
+    shutdown: bool,
+    output: &'a mut dyn Write,
+}
+
+impl Server<'_> {
+    fn publish(&mut self, uri: &str) {
+        let diagnostics = match self.documents.get(uri) {
+            Some(source) => parse(source).diagnostics(uri),
+            None => Json::Array(vec![]), //closed, so its diagnostics go away
+        };
+        notify(self.output, "textDocument/publishDiagnostics", Json::object(vec![("uri", Json::str(uri)), ("diagnostics", diagnostics)]));
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) {
+        let uri = params.get("textDocument").and_then(|x| x.get("uri")).and_then(Json::as_str).unwrap_or("").to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").and_then(|x| x.get("text")).and_then(Json::as_str);
+                self.documents.insert(uri.clone(), text.unwrap_or("").to_string());
+            },
+            "textDocument/didChange" => {
+                let Some(Json::Array(changes)) = params.get("contentChanges") else { return };
+                let Some(text) = changes.last().and_then(|x| x.get("text")).and_then(Json::as_str) else { return };
+                self.documents.insert(uri.clone(), text.to_string());
+            },
+            "textDocument/didClose" => { self.documents.remove(&uri); },
+            _ => return, //`initialized`, `$/cancelRequest` and the like
+        }
+        self.publish(&uri);
+    }
+
+    //the document a request is about, and the offset of its `position`
+    fn document(&self, params: &Json) -> Result<(String, &str, usize), (i32, String)> {
+        let uri = params.get("textDocument").and_then(|x| x.get("uri")).and_then(Json::as_str).unwrap_or("");
+        let Some(source) = self.documents.get(uri) else {
+            return Err((INVALID_PARAMS, format!("Document {} is not open.", uri)));
+        };
+        let offset = params.get("position").and_then(|x| offset_at(source, x)).unwrap_or(0);
+        Ok((uri.to_string(), source, offset))
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Response {
+        if self.shutdown {
+            return Err((INVALID_REQUEST, "The server is shutting down.".to_string()));
+        }
+        match method {
+            "initialize" => Ok(initialize()),
+            "shutdown" => { self.shutdown = true; Ok(Json::Null) },
+            "textDocument/definition" => {
+                let (uri, source, offset) = self.document(params)?;
+                Ok(definition(&parse(source), &uri, offset))
+            },
+            "textDocument/hover" => {
+                let (_, source, offset) = self.document(params)?;
+                Ok(hover(&parse(source), offset))
+            },
+            "textDocument/completion" => {
+                let (_, source, offset) = self.document(params)?;
+                Ok(completion(source, offset))
+            },
+            "textDocument/documentSymbol" => {
+                let (_, source, _) = self.document(params)?;
+                let parsed = parse(source);
+                Ok(Json::Array(parsed.symbols(&parsed.root)))
+            },
+            "textDocument/formatting" => {
+                let (_, source, _) = self.document(params)?;
+                Ok(formatting(&parse(source)))
+            },
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method {}.", method))),
+        }
+    }
+}
+
+fn initialize() -> Json {
+    let capabilities = Json::object(vec![
+        ("textDocumentSync", Json::Number(SYNC_FULL)),
+        ("definitionProvider", Json::Bool(true)),
+        ("hoverProvider", Json::Bool(true)),
+        ("completionProvider", Json::object(vec![("triggerCharacters", Json::Array(vec![Json::str(":")]))])),
+        ("documentSymbolProvider", Json::Bool(true)),
+        ("documentFormattingProvider", Json::Bool(true)),
+    ]);
+    let info = Json::object(vec![("name", Json::str("oko")), ("version", Json::str(env!("CARGO_PKG_VERSION")))]);
+    Json::object(vec![("capabilities", capabilities), ("serverInfo", info)])
+}
+
+fn definition(parsed: &Parsed, uri: &str, offset: usize) -> Json {
+    let references = checker::check(&parsed.root).references;
+    let Some(reference) = references.iter().find(|x| x.span.offset <= offset && offset <= x.span.offset + x.span.len) else {
+        return Json::Null;
+    };
+    let span = match reference.definition {
+        Definition::Var(x) | Definition::Fun(x, _) => x,
+    };
+    Json::object(vec![("uri", Json::str(uri)), ("range", range(parsed.source, span))])
+}
+
+fn hover(parsed: &Parsed, offset: usize) -> Json {
+    let tokens = parsed.stream.tokens();
+    let Some(index) = parsed.token_at(offset) else { return Json::Null };
+    let TokenClass::Identifier(ref name) = tokens[index].data else { return Json::Null };
+
+    //`io::println`
+    if index >= 2 && tokens[index - 1].data == TokenClass::Namespace {
+        let TokenClass::Identifier(ref mod_name) = tokens[index - 2].data else { return Json::Null };
+        let Some(member) = builtins::lookup(mod_name, name) else { return Json::Null };
+        return hover_text(&builtin_signature(mod_name, member), member.doc);
+    }
+
+    //the name of a declaration, or a call
+    let declared = match index.checked_sub(1).map(|x| &tokens[x]) {
+        Some(keyword) if keyword.data == TokenClass::Keyword("fun".to_string()) => {
+            let mut visitor = FunAt { offset: keyword.span.offset, found: None };
+            visitor.visit_block(&parsed.root);
+            visitor.found
+        },
+        _ => checker::check(&parsed.root).references.into_iter()
+            .find(|x| x.span.offset == tokens[index].span.offset)
+            .and_then(|x| match x.definition { Definition::Fun(span, decl) => Some((span, decl)), _ => None }),
+    };
+    let Some((span, decl)) = declared else { return Json::Null };
+    hover_text(&formatter::signature(parsed.source, &decl), &parsed.doc_comments(span))
+}
+
+//members of the module before `::`, the editor narrows them down by what has been typed after it
+fn completion(source: &str, offset: usize) -> Json {
+    let is_name = |x: char| x.is_alphanumeric() || x == '_';
+    let before = source[..offset].trim_end_matches(is_name);
+    let Some(before) = before.strip_suffix("::") else { return Json::Array(vec![]) };
+    let mod_name = &before[before.trim_end_matches(is_name).len()..];
+    let Some(module) = builtins::MODULES.iter().find(|x| x.name == mod_name) else { return Json::Array(vec![]) };
+
+    let items = module.members.iter().map(|x| Json::object(vec![
+        ("label", Json::str(x.name)),
+        ("kind", Json::Number(COMPLETION_FUNCTION)),
+        ("detail", Json::String(builtin_signature(mod_name, x))),
+        ("documentation", Json::str(x.doc)),
+    ]));
+    Json::Array(items.collect())
+}
+
+//a single edit replacing the whole document, or none if it is formatted already
+fn formatting(parsed: &Parsed) -> Json {
+    if !parsed.stream.diagnostics().is_empty() { return Json::Null }
+
+    let formatted = formatter::format(parsed.source, parsed.stream.comments(), &parsed.root);
+    if formatted == parsed.source { return Json::Array(vec![]) }
+    let whole = Span { offset: 0, len: parsed.source.len(), line: 1, col: 1 };
+    Json::Array(vec![Json::object(vec![("range", range(parsed.source, whole)), ("newText", Json::String(formatted))])])
+}
+
+
+//serves until the editor closes the connection, returns whether it asked for a shutdown before that
+pub fn run() -> bool {
+    return serve(&mut io::stdin().lock(), &mut io::stdout().lock());
+}
+
+fn serve(input: &mut impl BufRead, output: &mut dyn Write) -> bool {
+    let mut server = Server { documents: HashMap::new(), shutdown: false, output };
+
+    while let Some(message) = read_message(input) {
+        let message = match message {
+            Ok(x) => x,
+            Err(msg) => { respond(server.output, Json::Null, Err((PARSE_ERROR, msg))); continue },
+        };
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            continue; //a response to a request of ours, there are none
+        };
+
+        match message.get("id") {
+            Some(id) => {
+                let response = server.request(method, &params);
+                respond(server.output, id.clone(), response);
+            },
+            None if method == "exit" => break,
+            None => server.notification(method, &params),
+        }
+    }
+    server.shutdown
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(message: Json) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn request(id: f64, method: &str, params: Json) -> String {
+        frame(Json::object(vec![("jsonrpc", Json::str("2.0")), ("id", Json::Number(id)), ("method", Json::str(method)), ("params", params)]))
+    }
+
+    fn notification(method: &str, params: Json) -> String {
+        frame(Json::object(vec![("jsonrpc", Json::str("2.0")), ("method", Json::str(method)), ("params", params)]))
+    }
+
+    fn at(line: f64, character: f64) -> Json {
+        Json::object(vec![("line", Json::Number(line)), ("character", Json::Number(character))])
+    }
+
+    //the messages the server sends back for `input`, and whether it was shut down
+    fn session(input: &str) -> (Vec<Json>, bool) {
+        let mut output = vec![];
+        let shutdown = serve(&mut input.as_bytes(), &mut output);
+        let mut output = output.as_slice();
+        let mut messages = vec![];
+        while let Some(message) = read_message(&mut output) {
+            messages.push(message.unwrap());
+        }
+        (messages, shutdown)
+    }
+
+    #[test]
+    fn positions_break_lines_like_the_lexer() {
+        let source = "a\rbé\r\nc\nd";
+        for (offset, line, character) in [(0, 0, 0), (2, 1, 0), (3, 1, 1), (5, 1, 2), (7, 2, 0), (9, 3, 0)] {
+            assert_eq!(position(source, offset), at(line as f64, character as f64), "{}", offset);
+            assert_eq!(offset_at(source, &at(line as f64, character as f64)), Some(offset));
+        }
+        //past the end of a line stays on it
+        assert_eq!(offset_at(source, &at(1.0, 10.0)), Some(5));
+        assert_eq!(offset_at(source, &at(4.0, 0.0)), None);
+    }
+
+    #[test]
+    fn initialize_open_and_hover() {
+        let uri = Json::str("file:///x.oko");
+        let source = "/// Adds one.\rfun inc(x) { return x + 1; }\r\ny := inc(1);";
+        let document = Json::object(vec![("uri", uri.clone()), ("text", Json::str(source))]);
+        let hover_at = |line, character| Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", uri.clone())])),
+            ("position", at(line, character)),
+        ]);
+        let input = [
+            request(1.0, "initialize", Json::object(vec![])),
+            notification("initialized", Json::object(vec![])),
+            notification("textDocument/didOpen", Json::object(vec![("textDocument", document)])),
+            request(2.0, "textDocument/hover", hover_at(2.0, 6.0)),
+            request(3.0, "textDocument/hover", hover_at(2.0, 0.0)),
+            "Content-Length: 5\r\n\r\n{nope".to_string(),
+            request(4.0, "shutdown", Json::Null),
+            notification("exit", Json::Null),
+        ].concat();
+
+        let (messages, shutdown) = session(&input);
+        assert!(shutdown);
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[0].get("id"), Some(&Json::Number(1.0)));
+        assert_eq!(messages[0].get("result"), Some(&initialize()));
+
+        let diagnostics = messages[1].get("params").and_then(|x| x.get("diagnostics")).unwrap();
+        let Json::Array(diagnostics) = diagnostics else { panic!("{}", diagnostics) };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("message"), Some(&Json::str("Variable y is never used. (unused-variable)")));
+        assert_eq!(diagnostics[0].get("range"), Some(&Json::object(vec![("start", at(2.0, 0.0)), ("end", at(2.0, 1.0))])));
+
+        let hover = messages[2].get("result").and_then(|x| x.get("contents")).and_then(|x| x.get("value"));
+        assert_eq!(hover, Some(&Json::str("```oko\nfun inc(x)\n```\n\nAdds one.")));
+        assert_eq!(messages[3].get("result"), Some(&Json::Null));
+        assert_eq!(messages[4].get("error").and_then(|x| x.get("code")), Some(&Json::Number(PARSE_ERROR as f64)));
+        assert_eq!(messages[5], Json::object(vec![("jsonrpc", Json::str("2.0")), ("id", Json::Number(4.0)), ("result", Json::Null)]));
+    }
+}
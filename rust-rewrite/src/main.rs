@@ -1,42 +1,248 @@
-use std::{collections::HashMap, fs};
 
-use parser::Nodeable;
+use std::{collections::HashMap, fs, io, path::Path, rc::Rc};
 
+use cli::{Command, DumpFormat, Input, RunOptions};
 
+mod ast;
+mod builtins;
+mod cli;
+mod json;
+mod dump;
 mod lexer;
 mod parser;
+mod checker;
+mod lint;
 mod executor;
+mod formatter;
+mod tailcall;
+mod repl;
+mod debugger;
+mod profiler;
+mod coverage;
+mod tester;
+mod lsp;
 
-fn error(msg: &str) -> ! {
-    eprint!("Error: {}\n", msg);
-    std::process::exit(1);
+
+//exit codes, so scripts and editors can tell what kind of failure happened
+pub const EXIT_RUNTIME: i32 = 1;
+pub const EXIT_USAGE:   i32 = 2;
+pub const EXIT_SYNTAX:  i32 = 3;
+
+fn error(code: i32, msg: &str) -> ! {
+    eprintln!("Error: {}", msg);
+    std::process::exit(code);
 }
 
 
-fn main() {
+fn read_input(input: &Input) -> String {
+    match input {
+        Input::File(path) => match fs::read_to_string(path) {
+            Ok(x) => x,
+            Err(_) => error(EXIT_USAGE, format!("Unable to read source file '{}'.", path).as_str()),
+        },
+        Input::Stdin => match io::read_to_string(io::stdin()) {
+            Ok(x) => x,
+            Err(_) => error(EXIT_USAGE, "Unable to read the program from stdin."),
+        },
+        Input::Code(x) => x.clone(),
+    }
+}
+
+fn new_scope(args: Vec<String>, max_call_depth: Option<usize>) -> executor::Scope {
+    executor::Scope {
+        vars: HashMap::new(),
+        funs: HashMap::new(),
+        args: Rc::new(args),
+        frame: None,
+        max_call_depth: max_call_depth.unwrap_or(executor::DEFAULT_MAX_CALL_DEPTH),
+        hooks: None,
+    }
+}
+
+fn abort_on_errors(source: &str, stream: &lexer::Stream) {
+    let count = lexer::report_all(source, stream);
+    if count > 0 {
+        error(EXIT_SYNTAX, format!("Aborting due to {} syntax error(s).", count).as_str());
+    }
+}
 
-    let mut args = std::env::args();
-    _ = args.next(); //first arg is exec path
 
-    let Some(source_path) = args.next() else {
-        error("No source path argument provided.");
-    };
+fn run(input: Input, args: Vec<String>, options: RunOptions) {
+    let source = read_input(&input);
 
-    let Ok(source) = fs::read_to_string(source_path) else {
-        error("Unable to read source file.");
+    let mut stream = lexer::lex(&source);
+    if options.dump_tokens {
+        print!("{}", dump::tokens(&stream));
+    }
+
+    let root = parser::parse_program(&mut stream);
+    match options.dump_ast {
+        Some(DumpFormat::Tree) => print!("{}", dump::tree(&dump::block(&root))),
+        Some(DumpFormat::Json) => println!("{}", dump::block(&root).pretty()),
+        None => {},
+    }
+
+    abort_on_errors(&source, &stream);
+
+    //dumping is for inspecting the program, not running it
+    if options.dump_tokens || options.dump_ast.is_some() {
+        return;
+    }
+
+    let mut scope = new_scope(args, options.max_call_depth);
+    let result = match (options.profile, options.coverage) {
+        (Some(path), _) => profiler::run(&source, &root, &mut scope, &path),
+        (_, Some(path)) => coverage::run(input.name(), &root, &mut scope, &path),
+        (None, None) => { root.eval(&mut scope); Ok(()) },
     };
+    if let Err(msg) = result {
+        error(EXIT_RUNTIME, &msg);
+    }
+}
 
+fn debug(input: Input, args: Vec<String>) {
+    let source = read_input(&input);
+    let mut stream = lexer::lex(&source);
+    let root = parser::parse_program(&mut stream);
+    abort_on_errors(&source, &stream);
+
+    debugger::run(stream.source(), &root, &mut new_scope(args, None));
+}
+
+//the `.okolint` file next to the program, or in the working directory for stdin and `-e` code
+fn lint_config(input: &Input) -> lint::Config {
+    let dir = match input {
+        Input::File(path) => Path::new(path).parent().unwrap_or(Path::new("")),
+        Input::Stdin | Input::Code(_) => Path::new(""),
+    };
+    match lint::Config::load(dir) {
+        Ok(x) => x,
+        Err(msg) => error(EXIT_USAGE, &msg),
+    }
+}
 
+fn check(input: Input) {
+    let source = read_input(&input);
     let mut stream = lexer::lex(&source);
-    let root = parser::StatSeq::parse(&mut stream);
-    
-    let mut scope = executor::Scope {
-        vars: HashMap::new(),
-        funs: HashMap::new(),
-        ret_val: executor::Obj::Invalid,
-        ret_flag: false
+    let root = parser::parse_program(&mut stream);
+    abort_on_errors(&source, &stream);
+
+    let diagnostics = checker::check(&root).diagnostics;
+    for diagnostic in &diagnostics {
+        lexer::report(&source, diagnostic);
+    }
+    for warning in lint::lint(&source, stream.comments(), &root, &lint_config(&input)) {
+        lexer::warn(&source, &warning);
+    }
+    if !diagnostics.is_empty() {
+        error(EXIT_RUNTIME, format!("{} failed the check with {} error(s).", input.name(), diagnostics.len()).as_str());
+    }
+}
+
+fn test(paths: Vec<String>) {
+    match tester::run(&paths, &new_scope(vec![], None)) {
+        Ok(true) => {},
+        Ok(false) => error(EXIT_RUNTIME, "Some tests failed."),
+        Err(msg) => error(EXIT_USAGE, &msg),
+    }
+}
+
+
+//files are rewritten in place, stdin and `-e` code is printed.
+//with `check`, nothing is written and unformatted programs make the command fail
+fn fmt(inputs: Vec<Input>, check: bool) {
+    let mut unformatted = 0;
+    for input in &inputs {
+        let source = read_input(input);
+        let mut stream = lexer::lex(&source);
+        let root = parser::parse_program(&mut stream);
+        abort_on_errors(&source, &stream);
+
+        let formatted = formatter::format(&source, stream.comments(), &root);
+        if check {
+            if formatted != source {
+                eprintln!("{} is not formatted.", input.name());
+                unformatted += 1;
+            }
+            continue;
+        }
+
+        match input {
+            Input::File(path) if formatted != source => {
+                if fs::write(path, &formatted).is_err() {
+                    error(EXIT_USAGE, format!("Unable to write source file '{}'.", path).as_str());
+                }
+            },
+            Input::File(_) => {},
+            _ => print!("{}", formatted),
+        }
+    }
+
+    if unformatted > 0 {
+        error(EXIT_RUNTIME, format!("{} program(s) are not formatted, run `oko fmt` on them.", unformatted).as_str());
+    }
+}
+
+
+fn run_command() {
+    let base = 0u8;
+    executor::set_stack(std::hint::black_box(&base) as *const u8 as usize, STACK_SIZE - STACK_RESERVE);
+
+    let command = match cli::parse(std::env::args().skip(1).collect()) { //first arg is exec path
+        Ok(x) => x,
+        Err(msg) => {
+            eprintln!("Error: {}\n\n{}", msg, cli::USAGE);
+            std::process::exit(EXIT_USAGE);
+        },
     };
 
-    root.eval(&mut scope);
+    match command {
+        Command::Run { input, args, options } => run(input, args, options),
+        Command::Check(input) => check(input),
+        Command::Debug { input, args } => debug(input, args),
+        Command::Fmt { inputs, check } => fmt(inputs, check),
+        Command::Test(paths) => test(paths),
+        Command::Repl    => repl::run(&mut new_scope(vec![], None)),
+        //the protocol wants a failure when the editor quits without shutting the server down first
+        Command::Lsp     => if !lsp::run() { std::process::exit(EXIT_RUNTIME) },
+        Command::License => println!("{}", cli::LICENSE),
+        Command::Version => println!("oko {}", env!("CARGO_PKG_VERSION")),
+        Command::Help    => println!("{}", cli::USAGE),
+    }
+}
+
+//reports the runtime error that nothing else caught, along with the oko call stack
+fn run_interpreter() {
+    if let Err(x) = executor::catch(run_command) {
+        eprintln!("{}", executor::format_error(&x));
+        std::process::exit(EXIT_RUNTIME);
+    }
+}
+
+//every oko call nests several calls of the interpreter,
+//so it runs on a thread with a far larger stack than the main thread gets.
+//usually the call depth limit stops runaway recursion long before the stack runs out,
+//with a higher `--max-call-depth` calls also stop once they get within `STACK_RESERVE` of the end
+const STACK_SIZE: usize = 1024 * 1024 * 1024;
+//room for whatever runs after the last call that was allowed, f.e. builtins and deeply nested expressions
+const STACK_RESERVE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    //runtime errors are reported by whoever stops them, the default message is only for bugs
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if info.payload().is::<executor::Unwinding>() { return }
+        default_hook(info);
+    }));
+
+    let interpreter = std::thread::Builder::new()
+        .name("interpreter".to_string())
+        .stack_size(STACK_SIZE)
+        .spawn(run_interpreter);
 
+    match interpreter.map(|x| x.join()) {
+        Ok(Ok(())) => {},
+        Ok(Err(_)) => std::process::exit(101), //a panic, the message has already been printed
+        Err(_) => error(EXIT_RUNTIME, "Unable to start the interpreter thread."),
+    }
 }
@@ -1,14 +1,19 @@
 
-use core::fmt;
+use std::iter::Peekable;
 use std::rc::Rc;
+use std::str::Chars;
 
-use crate::lexer::{self, Stream};
-use crate::executor;
+use crate::ast::{self, Expr, ExprKind, Stmt, StmtKind, StatSeq};
+use crate::lexer::{self, Stream, SyntaxError};
+use crate::tailcall;
 type Streaming<'a> = &'a mut lexer::Stream;
 
+//errors are recorded in the stream, `Err` just tells the caller to bail out
+type Parsed<T> = Result<T, SyntaxError>;
+
 const UNARY_OPS: [&str; 2] = ["!", "-"];
 
-fn get_op_precedence(op: &str) -> u32 {
+pub fn get_op_precedence(op: &str) -> u32 {
     match op {
     	"||" => 1,
     	"&&" => 2,
@@ -35,357 +40,480 @@ fn get_op_precedence(op: &str) -> u32 {
 
 
 
-pub trait Nodeable: fmt::Debug {
-    fn eval(&self, scope: &mut executor::Scope) -> executor::Obj;
-}
-
-pub type Node = Box<dyn Nodeable>;
-
-
-
-#[derive(Debug)] pub struct StatSeq          { pub nodes: Vec<Node> } //program is just sequence of statements
-#[derive(Debug)] pub struct ImportStat       { pub mod_name: String }
-#[derive(Debug)] pub struct VariableAssign   { pub var_name: String, pub op: lexer::TokenClass, pub expr: Node }
-#[derive(Debug)] pub struct BinaryExpr       { pub op: String, pub left: Node, pub right: Node }
-#[derive(Debug)] pub struct UnaryExpr        { pub op: String, pub operand: Node }
-#[derive(Debug)] pub struct IntLiteral       { pub value: u64 }
-#[derive(Debug)] pub struct FloatLiteral     { pub value: f64 }
-#[derive(Debug)] pub struct StrLiteral       { pub value: String }
-#[derive(Debug)] pub struct Variable         { pub name:  String } 
-#[derive(Debug)] pub struct FunctionCall     { pub name: String, pub args: Vec<Node> }
-#[derive(Debug)] pub struct ModAccess        { pub mod_name: String, pub member: FunctionCall }
-#[derive(Debug)] pub struct ArrayLiteral     { pub elem: Vec<Node> }
-#[derive(Debug)] pub struct ReturnStat       { pub expr: Option<Node> }
-#[derive(Debug)] pub struct FunctionDeclare  { pub name: String, pub args: Vec<String>, pub body: Rc<StatSeq> }
-#[derive(Debug)] pub struct ExprStat         { pub expr: Node }
-#[derive(Debug)] pub struct IfStat           { pub condition: Node, pub if_block: StatSeq, pub else_block: Option<Node> }
-#[derive(Debug)] pub struct WhileStat        { pub condition: Node, pub body: StatSeq }
-#[derive(Debug)] pub struct ForStat          { pub elem_name: String, pub array: Node, pub body: StatSeq }
-
+fn parse_block(stream: Streaming) -> Parsed<StatSeq> {
+    let start = stream.span();
+    stream.expect(lexer::TokenClass::CurlyOpen)?;
+    let mut block = StatSeq::parse(stream);
+    stream.expect(lexer::TokenClass::CurlyClose)?;
 
-fn parse_block(stream: Streaming) -> StatSeq {
-    stream.expect(lexer::TokenClass::CurlyOpen);
-    let block = StatSeq::parse(stream);
-    stream.expect(lexer::TokenClass::CurlyClose);
-    return block;
+    block.span = stream.span_from(start);
+    Ok(block)
 }
 
-fn parse_condition(stream: Streaming) -> Node {
-    stream.expect(lexer::TokenClass::ParenOpen);
-    let expr = parse_expr(stream);
-    stream.expect(lexer::TokenClass::ParenClose);
-    return expr;
+fn parse_condition(stream: Streaming) -> Parsed<Expr> {
+    stream.expect(lexer::TokenClass::ParenOpen)?;
+    let expr = parse_expr(stream)?;
+    stream.expect(lexer::TokenClass::ParenClose)?;
+    Ok(expr)
 }
 
 
 
-impl IfStat {
-    fn parse(stream: Streaming) -> Self {
+impl ast::IfStat {
+    fn parse(stream: Streaming) -> Parsed<Self> {
         stream.maybe(lexer::TokenClass::Keyword("if".to_string()));
-        let condition: Node  = parse_condition(stream);
-        let if_block: StatSeq = parse_block(stream);
+        let condition: Expr  = parse_condition(stream)?;
+        let if_block: StatSeq = parse_block(stream)?;
 
         let else_block = match stream.peek() {
             None => None,
-            Some(ref x) => {
+            Some(x) => {
                 match x.data {
-                    lexer::TokenClass::Keyword(ref x) if x == "elif" => 
-                        { stream.next(); Some(Box::new(IfStat::parse(stream)) as Node) },
-                    lexer::TokenClass::Keyword(ref x) if x == "else" => 
-                        { stream.next(); Some(Box::new(parse_block(stream)) as Node)   },
+                    lexer::TokenClass::Keyword(ref x) if x == "elif" =>
+                        { stream.next(); Some(ast::ElseBlock::Elif(Box::new(ast::IfStat::parse(stream)?))) },
+                    lexer::TokenClass::Keyword(ref x) if x == "else" =>
+                        { stream.next(); Some(ast::ElseBlock::Else(parse_block(stream)?))                  },
                     _ => None,
             }},
         };
 
-        IfStat { condition, if_block, else_block }
+        Ok(ast::IfStat { condition, if_block, else_block })
     }
 }
 
 
 
+//takes the identifier that is up next, or errors at whatever is there instead
+fn parse_identifier(stream: Streaming, msg: &str) -> Parsed<String> {
+    let Some(token) = stream.peek() else { return Err(stream.error(msg)); };
+    let lexer::TokenClass::Identifier(ref name) = token.data else { return Err(stream.error(msg)); };
+    let name = name.clone();
+    stream.next();
+    Ok(name)
+}
+
 fn lookhead_fn_call(stream: &Stream) -> bool {
-    match stream.lookhead(1) {
-        Some(lexer::TokenClass::ParenOpen) => true,
-        _ => false,
-    }
+    matches!(stream.lookhead(1), Some(lexer::TokenClass::ParenOpen))
 }
 
 fn lookhead_mod(stream: &Stream) -> bool {
-    match stream.lookhead(1) {
-        Some(lexer::TokenClass::Namespace) => true,
-        _ => false,
+    matches!(stream.lookhead(1), Some(lexer::TokenClass::Namespace))
+}
+
+
+
+
+//parses the expression inside of a `{...}` segment of a string literal,
+//`end` is where the closing brace is
+fn parse_interpolation(stream: Streaming, snippet: &[(char, lexer::Span)], end: lexer::Span) -> Expr {
+    let start = snippet.first().map_or(end, |x| x.1);
+    let mut inner = lexer::lex_snippet(stream.source(), snippet, end);
+
+    let expr = if inner.peek().is_none() {
+        Err(inner.error_at(start, "Empty interpolation in string literal."))
+    } else {
+        parse_expr(&mut inner).and_then(|expr| match inner.peek() {
+            Some(token) => {
+                let msg = format!("Unexpected {} in string interpolation.", token.data);
+                Err(inner.error(msg.as_str()))
+            },
+            None => Ok(expr),
+        })
+    };
+
+    stream.absorb(inner);
+    expr.unwrap_or(Expr { kind: ExprKind::Error, span: start })
+}
+
+fn bump(chars: &mut Peekable<Chars>, pos: &mut lexer::Span) -> Option<char> {
+    let char = chars.next()?;
+    lexer::advance(pos, char, chars.peek().copied());
+    Some(char)
+}
+
+//the characters of a string literal along with where each of them is, and where the literal ends.
+//an escape sequence is a single character, which is where its `\` is
+fn unescape(raw: &str, span: lexer::Span) -> (Vec<(char, lexer::Span)>, lexer::Span) {
+    let mut out = vec![];
+    let mut chars = raw.chars().peekable();
+    let mut pos = lexer::Span { len: 0, ..span };
+    lexer::advance(&mut pos, '"', None); //opening quote
+
+    loop {
+        let at = pos;
+        let Some(char) = bump(&mut chars, &mut pos) else { break };
+        let char = match chars.peek().copied().and_then(lexer::escape_sequence) {
+            Some(x) if char == '\\' => { bump(&mut chars, &mut pos); x },
+            _ => char,
+        };
+        out.push((char, at));
     }
+    (out, pos)
 }
 
+//the index of the `}` that closes an interpolation, from `start` on.
+//the ones in strings nested inside of it don't count, f.e. in `{f("}}")}`,
+//and a `\` in those takes the character after it along, like the lexer does
+fn closing_brace(chars: &[(char, lexer::Span)], start: usize) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, &(char, _)) in chars.iter().enumerate().skip(start) {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '}' if !in_string => return Some(index),
+            _ => {},
+        }
+    }
+    None
+}
+
+//splits a string literal into plain text and `{expr}` segments,
+//`{{` and `}}` are used to write literal braces. `raw` is the literal as written, without its quotes
+fn parse_str_literal(stream: Streaming, raw: &str, span: lexer::Span) -> Parsed<ExprKind> {
+    let (chars, end) = unescape(raw, span);
+    //where the character at `index` is, or the end of the literal
+    let at = |index: usize| chars.get(index).map_or(end, |x| x.1);
+    if !chars.iter().any(|x| x.0 == '{' || x.0 == '}') {
+        return Ok(ExprKind::Str(chars.iter().map(|x| x.0).collect()));
+    }
+
+    let mut parts: Vec<Expr> = vec![];
+    let mut text = String::new();
+    let mut text_start = 0; //index of the first character of `text`
+    let mut index = 0;
+
+    while let Some(&(char, pos)) = chars.get(index) {
+        index += 1;
+        let next = chars.get(index).map(|x| x.0);
+        match char {
+            '{' if next == Some('{') => { index += 1; text.push('{'); },
+            '}' if next == Some('}') => { index += 1; text.push('}'); },
+            '}' => return Err(stream.error_at(lexer::Span { len: 1, ..pos }, "Unmatched '}' in string literal, use '}}' for a literal brace.")),
+            '{' => {
+                let Some(close) = closing_brace(&chars, index) else {
+                    return Err(stream.error_at(lexer::Span { len: 1, ..pos }, "Unterminated '{' in string literal, use '{{' for a literal brace."));
+                };
+
+                if !text.is_empty() {
+                    let start = at(text_start);
+                    let text_span = lexer::Span { len: pos.offset - start.offset, ..start };
+                    parts.push(Expr { kind: ExprKind::Str(std::mem::take(&mut text)), span: text_span });
+                }
+                parts.push(parse_interpolation(stream, &chars[index..close], at(close)));
+                index = close + 1;
+                text_start = index;
+            },
+            x => text.push(x),
+        }
+    }
+
+    if !text.is_empty() {
+        let start = at(text_start);
+        let text_span = lexer::Span { len: end.offset - start.offset, ..start };
+        parts.push(Expr { kind: ExprKind::Str(text), span: text_span });
+    }
+
+    Ok(ExprKind::Format(parts))
+}
 
 
+fn parse_primary_expr(stream: Streaming) -> Parsed<Expr> {
+    let Some(token) = stream.peek() else { return Err(stream.error("End of token stream while parsing primary expression.")); };
+    let span = token.span;
 
-fn parse_primary_expr(stream: Streaming) -> Node {
-    let Some(token) = stream.peek() else { stream.error("End of token stream while parsing primary expression."); };
-    
-    match token.data.clone() {
+    let kind = match token.data.clone() {
         lexer::TokenClass::Operator(ref op) if UNARY_OPS.contains(&op.as_str()) => {
             stream.next();
-            let operand = parse_expr_prec(stream, 9);
-            Box::new(UnaryExpr { operand, op: op.clone() }) as Node
+            let operand = parse_expr_prec(stream, 9)?;
+            ExprKind::Unary { operand: Box::new(operand), op: op.clone() }
         },
-        lexer::TokenClass::BracketOpen                               => {                Box::new(ArrayLiteral::parse(stream))       as Node },
-        lexer::TokenClass::Integer(x)                                => { stream.next(); Box::new(IntLiteral   { value: x         }) as Node },
-        lexer::TokenClass::Float(x)                                  => { stream.next(); Box::new(FloatLiteral { value: x         }) as Node },
-        lexer::TokenClass::String(ref x)                             => { stream.next(); Box::new(StrLiteral   { value: x.clone() }) as Node },
-        lexer::TokenClass::Identifier(_) if lookhead_fn_call(stream) => {                Box::new(FunctionCall::parse(stream))       as Node },
-        lexer::TokenClass::Identifier(_) if lookhead_mod(stream)     => {                Box::new(ModAccess::parse(stream))          as Node },
-        lexer::TokenClass::Identifier(ref x)                         => { stream.next(); Box::new(Variable     { name: x.clone()  }) as Node },
+        lexer::TokenClass::BracketOpen                               => {                ExprKind::Array(parse_array(stream)?)      },
+        lexer::TokenClass::Integer { value, text }                   => { stream.next(); ExprKind::Int { value, text }              },
+        lexer::TokenClass::Float { value, text }                     => { stream.next(); ExprKind::Float { value, text }            },
+        lexer::TokenClass::String(ref x)                             => { stream.next(); parse_str_literal(stream, x, span)?        },
+        lexer::TokenClass::Identifier(_) if lookhead_fn_call(stream) => {                ExprKind::Call(ast::FunctionCall::parse(stream)?) },
+        lexer::TokenClass::Identifier(_) if lookhead_mod(stream)     => {                parse_mod_access(stream)?                  },
+        lexer::TokenClass::Identifier(ref x)                         => { stream.next(); ExprKind::Variable(x.clone())              },
         lexer::TokenClass::ParenOpen     => {
             stream.next();
-            let expr = parse_expr(stream);
-            stream.expect(lexer::TokenClass::ParenClose);
-            expr
+            let expr = parse_expr(stream)?;
+            stream.expect(lexer::TokenClass::ParenClose)?;
+            expr.kind
         },
-        _ => stream.error("Invalid Syntax while parsing primary expression.")
+        _ => return Err(stream.error("Invalid Syntax while parsing primary expression."))
+    };
+
+    //`?` binds tighter than any operator, f.e. `-f()?` is `-(f()?)`
+    let mut expr = Expr { kind, span: stream.span_from(span) };
+    while let Some(lexer::TokenClass::Question) = stream.lookhead(0) {
+        stream.next();
+        expr = Expr { kind: ExprKind::Propagate(Box::new(expr)), span: stream.span_from(span) };
     }
-    
+    Ok(expr)
 }
 
-fn parse_expr(stream: Streaming) -> Node {
+fn parse_expr(stream: Streaming) -> Parsed<Expr> {
     parse_expr_prec(stream, 0)
 }
 
 
-fn parse_expr_prec(stream: Streaming, precedence: u32) -> Node {
-    let mut left = parse_primary_expr(stream);
- 
-    loop {
-        let Some(token) = stream.peek() else { break; };
+fn parse_expr_prec(stream: Streaming, precedence: u32) -> Parsed<Expr> {
+    let mut left = parse_primary_expr(stream)?;
+
+    while let Some(token) = stream.peek() {
         let lexer::TokenClass::Operator(ref op_ref) = token.data else { break; };
         if precedence > get_op_precedence(op_ref.as_str()) { break; }
         let op = op_ref.clone();
         stream.next();
 
-        let right = parse_expr_prec(stream, get_op_precedence(op.as_str()));
+        let right = parse_expr_prec(stream, get_op_precedence(op.as_str()))?;
 
-        left = Box::new(BinaryExpr { 
-            op, left, right
-        }) as Node;
+        let span = stream.span_from(left.span);
+        left = Expr {
+            kind: ExprKind::Binary { op, left: Box::new(left), right: Box::new(right) },
+            span,
+        };
     }
 
-    left
+    Ok(left)
 }
 
 
-fn parse_func_args(stream: Streaming) -> Vec<String> {
-    let mut out: Vec<String> = vec![];
-    stream.expect(lexer::TokenClass::ParenOpen);
+//parameters are `name`, `name := default` or a trailing `...name` collecting the remaining arguments
+fn parse_func_args(stream: Streaming) -> Parsed<(Vec<ast::Param>, Option<ast::Param>)> {
+    let mut out: Vec<ast::Param> = vec![];
+    let mut rest = None;
+    stream.expect(lexer::TokenClass::ParenOpen)?;
 
     while let Some(x) = stream.peek() {
+        let start = x.span;
         match x.data {
             lexer::TokenClass::Identifier(ref x) => {
-                let arg = x.clone();
+                let name = x.clone();
+                stream.next();
+
+                let mut default = None;
+                if let Some(lexer::TokenClass::Define) = stream.lookhead(0) {
+                    stream.next();
+                    default = Some(parse_expr(stream)?);
+                } else if out.last().is_some_and(|x| x.default.is_some()) {
+                    return Err(stream.error_at(start, "Parameters after one with a default value need a default value too."));
+                }
+
+                out.push(ast::Param { name, default, span: stream.span_from(start) });
+                stream.maybe(lexer::TokenClass::Comma);
+            },
+            lexer::TokenClass::Ellipsis => {
                 stream.next();
+                let name = parse_identifier(stream, "Expected identifier for rest parameter.")?;
+                rest = Some(ast::Param { name, default: None, span: stream.span_from(start) });
+
                 stream.maybe(lexer::TokenClass::Comma);
-                out.push(arg);
+                if stream.lookhead(0) != Some(&lexer::TokenClass::ParenClose) {
+                    return Err(stream.error("Rest parameter has to be the last one."));
+                }
             },
             lexer::TokenClass::ParenClose => { break; }
-            _ => stream.error("Expected identifier or closing parenthesis")
+            _ => return Err(stream.error("Expected identifier or closing parenthesis."))
         }
     }
 
-    stream.expect(lexer::TokenClass::ParenClose);
-    out
+    stream.expect(lexer::TokenClass::ParenClose)?;
+    Ok((out, rest))
 }
 
-impl WhileStat {
-    fn parse(stream: Streaming) -> Self {
-        stream.maybe(lexer::TokenClass::Keyword("while".to_string()));
-        let condition = parse_condition(stream);
-        let body = parse_block(stream);
-        WhileStat { condition, body }
-    }
+fn parse_while(stream: Streaming) -> Parsed<StmtKind> {
+    stream.maybe(lexer::TokenClass::Keyword("while".to_string()));
+    let condition = parse_condition(stream)?;
+    let body = parse_block(stream)?;
+    Ok(StmtKind::While { condition, body })
 }
 
-impl ForStat {
-    fn parse(stream: Streaming) -> Self {
-        stream.maybe(lexer::TokenClass::Keyword("for".to_string()));
-        stream.expect(lexer::TokenClass::ParenOpen);
-        let Some(token) = stream.pop() else { stream.error("End of token stream while parsing for loop."); };
-        let lexer::TokenClass::Identifier(ref elem_name_ref) = token.data else { stream.error("Expected identifier for element name"); };
-        let elem_name = elem_name_ref.clone();
-        stream.expect(lexer::TokenClass::ParenClose);
+fn parse_for(stream: Streaming) -> Parsed<StmtKind> {
+    stream.maybe(lexer::TokenClass::Keyword("for".to_string()));
+    stream.expect(lexer::TokenClass::ParenOpen)?;
+    let elem_span = stream.span();
+    let elem_name = parse_identifier(stream, "Expected identifier for element name.")?;
+    stream.expect(lexer::TokenClass::ParenClose)?;
 
-        stream.expect(lexer::TokenClass::ParenOpen);
-        let array = parse_expr(stream);
-        stream.expect(lexer::TokenClass::ParenClose);
+    stream.expect(lexer::TokenClass::ParenOpen)?;
+    let array = parse_expr(stream)?;
+    stream.expect(lexer::TokenClass::ParenClose)?;
 
-        let body = parse_block(stream);
+    let body = parse_block(stream)?;
 
-        ForStat { elem_name, array, body }
+    Ok(StmtKind::For { elem_name, elem_span, array, body })
+}
+
+fn parse_catch(stream: Streaming) -> Parsed<ast::Catch> {
+    stream.maybe(lexer::TokenClass::Keyword("catch".to_string()));
+    stream.expect(lexer::TokenClass::ParenOpen)?;
+    let span = stream.span();
+    let name = parse_identifier(stream, "Expected identifier for the caught error.")?;
+    stream.expect(lexer::TokenClass::ParenClose)?;
+    let body = parse_block(stream)?;
+    Ok(ast::Catch { name, span, body })
+}
+
+fn parse_try(stream: Streaming) -> Parsed<StmtKind> {
+    stream.maybe(lexer::TokenClass::Keyword("try".to_string()));
+    let body = parse_block(stream)?;
+
+    let catch = match stream.lookhead(0) {
+        Some(lexer::TokenClass::Keyword(x)) if x == "catch" => Some(parse_catch(stream)?),
+        _ => None,
+    };
+    let finally = match stream.lookhead(0) {
+        Some(lexer::TokenClass::Keyword(x)) if x == "finally" => { stream.next(); Some(parse_block(stream)?) },
+        _ => None,
+    };
+
+    if catch.is_none() && finally.is_none() {
+        return Err(stream.error("Expected `catch` or `finally` after the body of `try`."));
     }
+    Ok(StmtKind::Try { body, catch, finally })
 }
 
-impl ReturnStat {
-    fn parse(stream: Streaming) -> Self {
-        stream.maybe(lexer::TokenClass::Keyword("return".to_string()));
-        let Some(token) = stream.peek() else { stream.error("End of token stream while parsing return statement."); };
+fn parse_return(stream: Streaming) -> Parsed<StmtKind> {
+    stream.maybe(lexer::TokenClass::Keyword("return".to_string()));
+    let Some(token) = stream.peek() else { return Err(stream.error("End of token stream while parsing return statement.")); };
 
-        let expr: Option<Node> = match token.data {
-            lexer::TokenClass::EndOfStatement => None,
-            _ => Some(parse_expr(stream)),
-        };
+    let expr: Option<Expr> = match token.data {
+        lexer::TokenClass::EndOfStatement => None,
+        _ => Some(parse_expr(stream)?),
+    };
 
-        stream.expect(lexer::TokenClass::EndOfStatement);
+    stream.expect(lexer::TokenClass::EndOfStatement)?;
 
-        ReturnStat { expr }
-    }
+    Ok(StmtKind::Return { expr, tail_call: false })
 }
 
-impl FunctionDeclare {
-    fn parse(stream: Streaming) -> Self {
+impl ast::FunctionDeclare {
+    fn parse(stream: Streaming) -> Parsed<Self> {
         stream.maybe(lexer::TokenClass::Keyword("fun".to_string()));
-        let Some(token) = stream.pop() else { stream.error("End of token stream while parsing function delcaration."); };
-        let lexer::TokenClass::Identifier(ref name_ref) = token.data else { stream.error("Expected identifier."); };
-        let name = name_ref.clone();
-        
-        let args = parse_func_args(stream);
-        let body = parse_block(stream);
-
-        FunctionDeclare { name, args, body: Rc::new(body) }
+        let name = parse_identifier(stream, "Expected identifier for function name.")?;
+
+        let (args, rest) = parse_func_args(stream)?;
+        let body = parse_block(stream)?;
+
+        Ok(ast::FunctionDeclare { name, args, rest, body })
     }
 }
 
 
-fn parse_call_args(stream: Streaming) -> Vec<Node> {
-    let mut out: Vec<Node> = vec![];
-    stream.expect(lexer::TokenClass::ParenOpen);
+fn parse_call_args(stream: Streaming) -> Parsed<Vec<Expr>> {
+    let mut out: Vec<Expr> = vec![];
+    stream.expect(lexer::TokenClass::ParenOpen)?;
 
     while let Some(x) = stream.peek() {
         if lexer::TokenClass::ParenClose == x.data { break; };
-        out.push(parse_expr(stream));
+        out.push(parse_expr(stream)?);
         stream.maybe(lexer::TokenClass::Comma);
     }
 
-    stream.expect(lexer::TokenClass::ParenClose);
-    out
+    stream.expect(lexer::TokenClass::ParenClose)?;
+    Ok(out)
 }
 
-impl FunctionCall {
-    fn parse(stream: Streaming) -> Self {
-        let Some(token) = stream.pop() else { stream.error("End of token stream while parsing function call."); };
-        let lexer::TokenClass::Identifier(ref name_ref) = token.data else { stream.error("Expected identifier for function name."); };
-
-        let name = name_ref.clone();
-        let args = parse_call_args(stream);
+impl ast::FunctionCall {
+    fn parse(stream: Streaming) -> Parsed<Self> {
+        let name = parse_identifier(stream, "Expected identifier for function name.")?;
+        let args = parse_call_args(stream)?;
 
-        FunctionCall { name, args }
+        Ok(ast::FunctionCall { name, args })
     }
 }
 
-impl ModAccess {
-    fn parse(stream: Streaming) -> Self {
-        let Some(mod_token) = stream.pop() else { stream.error("End of token stream while parsing module access."); };
-        let lexer::TokenClass::Identifier(ref mod_ref) = mod_token.data else { stream.error("Expected module identifier."); };
-        let mod_name = mod_ref.clone();
-
-        stream.maybe(lexer::TokenClass::Namespace);
+fn parse_mod_access(stream: Streaming) -> Parsed<ExprKind> {
+    let mod_name = parse_identifier(stream, "Expected module identifier.")?;
 
-        let member = FunctionCall::parse(stream);
+    stream.maybe(lexer::TokenClass::Namespace);
 
-        ModAccess { mod_name, member }
+    let member = ast::FunctionCall::parse(stream)?;
 
-    }
+    Ok(ExprKind::ModAccess { mod_name, member })
 }
 
-impl ImportStat {
-    fn parse(stream: Streaming) -> Self {
-        stream.maybe(lexer::TokenClass::Keyword("import".to_string()));
-        let Some(mod_token) = stream.pop() else { stream.error("End of token stream while parsing module import."); };
-        let lexer::TokenClass::Identifier(ref mod_ref) = mod_token.data else { stream.error("Expected import identifier."); };
-        let mod_name = mod_ref.clone();
+fn parse_import(stream: Streaming) -> Parsed<StmtKind> {
+    stream.maybe(lexer::TokenClass::Keyword("import".to_string()));
+    let mod_name = parse_identifier(stream, "Expected import identifier.")?;
 
-        stream.expect(lexer::TokenClass::EndOfStatement);
+    stream.expect(lexer::TokenClass::EndOfStatement)?;
 
-        ImportStat { mod_name }
+    Ok(StmtKind::Import { mod_name })
+}
 
-    }
-    
-}
-
-impl VariableAssign {
-    fn parse(stream: Streaming) -> Self {
-        let Some(var_token) = stream.pop() else { stream.error("End of token stream while parsing variable assignment."); };
-        let lexer::TokenClass::Identifier(ref var_ref) = var_token.data else { stream.error("Expected variable name."); };
-        let var_name = var_ref.clone();
-
-        let Some(op_token) = stream.pop() else { stream.error("End of token stream while parsing variable assignment."); };
-        let op_data = op_token.data.to_owned();
-        let op = match op_data {
-            lexer::TokenClass::AssignOp(_) => op_data,
-            lexer::TokenClass::Assign => op_data,
-            lexer::TokenClass::Define => op_data,
-            _ => stream.error("Expected assignment operator."),
-        };
+fn parse_assign(stream: Streaming) -> Parsed<StmtKind> {
+    let var_name = parse_identifier(stream, "Expected variable name.")?;
 
-        let expr = parse_expr(stream);
+    let Some(op_token) = stream.peek() else { return Err(stream.error("End of token stream while parsing variable assignment.")); };
+    let op = match op_token.data {
+        lexer::TokenClass::AssignOp(ref x) => ast::AssignOp::from_symbol(x),
+        lexer::TokenClass::Assign => Some(ast::AssignOp::Assign),
+        lexer::TokenClass::Define => Some(ast::AssignOp::Define),
+        _ => None,
+    };
+    let Some(op) = op else { return Err(stream.error("Expected assignment operator.")); };
+    stream.next();
 
-        stream.expect(lexer::TokenClass::EndOfStatement);
+    let expr = parse_expr(stream)?;
 
-        VariableAssign { var_name, op, expr }
-    }
+    stream.expect(lexer::TokenClass::EndOfStatement)?;
+
+    Ok(StmtKind::Assign { var_name, op, expr })
 }
 
-impl ArrayLiteral {
-    fn parse(stream: Streaming) -> Self {
-        stream.expect(lexer::TokenClass::BracketOpen);
-        
-        let mut elem: Vec<Node> = vec![];
+fn parse_array(stream: Streaming) -> Parsed<Vec<Expr>> {
+    stream.expect(lexer::TokenClass::BracketOpen)?;
 
-        while let Some(token) = stream.peek() {
-            if let lexer::TokenClass::BracketClose = token.data { break; }
-            elem.push(parse_expr(stream));
-            stream.maybe(lexer::TokenClass::Comma);
-        }
+    let mut elem: Vec<Expr> = vec![];
 
-        stream.expect(lexer::TokenClass::BracketClose);
-        ArrayLiteral { elem }
+    while let Some(token) = stream.peek() {
+        if let lexer::TokenClass::BracketClose = token.data { break; }
+        elem.push(parse_expr(stream)?);
+        stream.maybe(lexer::TokenClass::Comma);
     }
+
+    stream.expect(lexer::TokenClass::BracketClose)?;
+    Ok(elem)
 }
 
 
-impl ExprStat {
-    fn parse(stream: Streaming) -> Self {
-        let expr = parse_expr(stream);
-        stream.expect(lexer::TokenClass::EndOfStatement);
-        ExprStat { expr }
-    }
+fn parse_expr_stat(stream: Streaming) -> Parsed<StmtKind> {
+    let expr = parse_expr(stream)?;
+    stream.expect(lexer::TokenClass::EndOfStatement)?;
+    Ok(StmtKind::Expr(expr))
 }
 
 
 fn lookhead_assign(stream: &lexer::Stream) -> bool {
-    match stream.lookhead(1) {
-        Some(lexer::TokenClass::AssignOp(_)) => true,
-        Some(lexer::TokenClass::Assign)      => true,
-        Some(lexer::TokenClass::Define)      => true,
-        _ => false
-    }
+    matches!(
+        stream.lookhead(1),
+        Some(lexer::TokenClass::AssignOp(_)) | Some(lexer::TokenClass::Assign) | Some(lexer::TokenClass::Define)
+    )
 }
 
 
-fn parse_statement(stream: Streaming) -> Option<Node> {
-    let Some(ref token) = stream.peek() else { return None };
+fn parse_statement(stream: Streaming) -> Option<Parsed<StmtKind>> {
+    let token = stream.peek()?;
 
     Some(match token.data {
-        lexer::TokenClass::Keyword(ref x) if x == "if"      => Box::new(IfStat::parse(stream)) as Node,
-        lexer::TokenClass::Keyword(ref x) if x == "while"   => Box::new(WhileStat::parse(stream)) as Node,
-        lexer::TokenClass::Keyword(ref x) if x == "for"     => Box::new(ForStat::parse(stream)) as Node,
-        lexer::TokenClass::Keyword(ref x) if x == "fun"     => Box::new(FunctionDeclare::parse(stream)) as Node,
-        lexer::TokenClass::Keyword(ref x) if x == "return"  => Box::new(ReturnStat::parse(stream)) as Node,
-        lexer::TokenClass::Keyword(ref x) if x == "import"  => Box::new(ImportStat::parse(stream)) as Node,
-        lexer::TokenClass::Identifier(_) if lookhead_assign(stream) => Box::new(VariableAssign::parse(stream)) as Node,
-        lexer::TokenClass::ParenOpen | 
-            lexer::TokenClass::Integer(_) | lexer::TokenClass::Float(_) | 
+        lexer::TokenClass::Keyword(ref x) if x == "if"      => ast::IfStat::parse(stream).map(StmtKind::If),
+        lexer::TokenClass::Keyword(ref x) if x == "while"   => parse_while(stream),
+        lexer::TokenClass::Keyword(ref x) if x == "for"     => parse_for(stream),
+        lexer::TokenClass::Keyword(ref x) if x == "try"     => parse_try(stream),
+        lexer::TokenClass::Keyword(ref x) if x == "fun"     => ast::FunctionDeclare::parse(stream).map(|x| StmtKind::Fun(Rc::new(x))),
+        lexer::TokenClass::Keyword(ref x) if x == "return"  => parse_return(stream),
+        lexer::TokenClass::Keyword(ref x) if x == "import"  => parse_import(stream),
+        lexer::TokenClass::Identifier(_) if lookhead_assign(stream) => parse_assign(stream),
+        lexer::TokenClass::ParenOpen |
+            lexer::TokenClass::Integer { .. } | lexer::TokenClass::Float { .. } |
             lexer::TokenClass::String(_) | lexer::TokenClass::Identifier(_) | lexer::TokenClass::Operator(_)
-            => Box::new(ExprStat::parse(stream)) as Node,
-    
-        _ => stream.error("Invalid syntax")
+            => parse_expr_stat(stream),
+
+        _ => Err(stream.error("Invalid syntax."))
     })
 
 }
@@ -399,22 +527,144 @@ fn parse_statement(stream: Streaming) -> Option<Node> {
 
 impl StatSeq {
     pub fn parse(stream: Streaming) -> Self {
-        let mut nodes: Vec<Node> = vec![];
-
-        loop {
-            let Some(node) = stream.peek() else { break };
-            if let lexer::TokenClass::CurlyClose = node.data { break };
+        let start = stream.span();
+        let mut stmts: Vec<Stmt> = vec![];
 
-            let Some(node) = parse_statement(stream) else { break };
-            nodes.push(node);
+        while let Some(token) = stream.peek() {
+            if let lexer::TokenClass::CurlyClose = token.data { break };
+
+            let span = token.span;
+            let Some(kind) = parse_statement(stream) else { break };
+
+            //skip over the broken statement and carry on,
+            //so that all syntax errors get reported in one go
+            let kind = kind.unwrap_or_else(|_| {
+                stream.synchronize();
+                StmtKind::Error
+            });
+            stmts.push(Stmt { kind, span: stream.span_from(span) });
         }
-        StatSeq { nodes }
+        StatSeq { stmts, span: stream.span_from(start) }
     }
 }
 
 
+//parses a whole program, which unlike a block doesn't end at a `}`
+pub fn parse_program(stream: Streaming) -> StatSeq {
+    let start = stream.span();
+    let mut root = StatSeq::parse(stream);
 
+    while let Some(token) = stream.peek() {
+        let span = token.span;
+        _ = stream.error("Unmatched closing curly brace.");
+        stream.next();
+
+        root.stmts.push(Stmt { kind: StmtKind::Error, span });
+        root.stmts.append(&mut StatSeq::parse(stream).stmts);
+    }
+
+    root.span = stream.span_from(start);
+    tailcall::mark(&mut root);
+    root
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Span;
+
+    //the first statement of `source`, which has to be an expression
+    fn parse_expr_stmt(source: &str) -> (Expr, Vec<lexer::Diagnostic>) {
+        let mut stream = lexer::lex(source);
+        let mut root = parse_program(&mut stream);
+        let StmtKind::Expr(expr) = root.stmts.remove(0).kind else { panic!("Not an expression statement.") };
+        (expr, stream.diagnostics().to_vec())
+    }
+
+    fn errors(source: &str) -> Vec<(u32, u32, String)> {
+        let mut stream = lexer::lex(source);
+        parse_program(&mut stream);
+        stream.diagnostics().iter().map(|x| (x.span.line, x.span.col, x.msg.clone())).collect()
+    }
+
+    fn parts(source: &str) -> Vec<(String, usize, usize, u32)> {
+        let (expr, diagnostics) = parse_expr_stmt(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let ExprKind::Format(parts) = expr.kind else { panic!("Not an interpolated string.") };
+        parts.iter().map(|x| {
+            let text = match &x.kind {
+                ExprKind::Str(x) => x.clone(),
+                x => format!("{:?}", x).split([' ', '(']).next().unwrap().to_string(),
+            };
+            (text, x.span.offset, x.span.len, x.span.col)
+        }).collect()
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let (expr, _) = parse_expr_stmt("\"\\t\\\"q\\\"\\n\\e\\x\";");
+        assert!(matches!(expr.kind, ExprKind::Str(x) if x == "\t\"q\"\n\x1B\\x"));
+    }
+
+    #[test]
+    fn interpolation_spans_are_exact_after_escapes() {
+        //  "\t\"é\" {x}{{}}\n"
+        //  0 1  3  56 8 9 10   15
+        assert_eq!(parts("\"\\t\\\"é\\\" {x}{{}}\\n\";"), vec![
+            ("\t\"é\" ".to_string(), 1, 9, 2),
+            ("Variable".to_string(), 11, 1, 11),
+            ("{}\n".to_string(), 13, 6, 13),
+        ]);
+    }
+
+    #[test]
+    fn strings_inside_interpolations() {
+        let (expr, _) = parse_expr_stmt("\"{f(\\\"a\\\")}\";");
+        let ExprKind::Format(parts) = expr.kind else { panic!() };
+        let ExprKind::Call(call) = &parts[0].kind else { panic!() };
+        assert!(matches!(&call.args[0].kind, ExprKind::Str(x) if x == "a"));
+        assert_eq!(call.args[0].span, Span { offset: 4, len: 5, line: 1, col: 5 });
+    }
 
+    #[test]
+    fn braces_in_strings_inside_interpolations() {
+        let (expr, diagnostics) = parse_expr_stmt(r#""{f(\"}}\")}!";"#);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let ExprKind::Format(parts) = expr.kind else { panic!() };
+        let ExprKind::Call(call) = &parts[0].kind else { panic!() };
+        assert!(matches!(&call.args[0].kind, ExprKind::Format(x) if matches!(&x[0].kind, ExprKind::Str(x) if x == "}")));
+        assert!(matches!(&parts[1].kind, ExprKind::Str(x) if x == "!"));
+
+        //interpolations nested in them
+        let (expr, diagnostics) = parse_expr_stmt(r#""{f(\"{x}\")}";"#);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let ExprKind::Format(parts) = expr.kind else { panic!() };
+        let ExprKind::Call(call) = &parts[0].kind else { panic!() };
+        assert!(matches!(&call.args[0].kind, ExprKind::Format(x) if matches!(&x[0].kind, ExprKind::Variable(x) if x == "x")));
+
+        //a single `}` is an error of the nested string, not the end of the interpolation
+        assert_eq!(errors(r#""{f(\"}\")}";"#), vec![(1, 6, "Unmatched '}' in string literal, use '}}' for a literal brace.".to_string())]);
+    }
 
+    #[test]
+    fn interpolation_errors_point_into_the_literal() {
+        assert_eq!(errors("import io;\nio::println(\"\\n{é-é)}\");"), vec![(2, 20, "Unexpected ParenClose in string interpolation.".to_string())]);
+        assert_eq!(errors("\"\\t{}\";"), vec![(1, 5, "Empty interpolation in string literal.".to_string())]);
+        assert_eq!(errors("\"é}\";"), vec![(1, 3, "Unmatched '}' in string literal, use '}}' for a literal brace.".to_string())]);
+        assert_eq!(errors("\"\\\"{a\";"), vec![(1, 4, "Unterminated '{' in string literal, use '{{' for a literal brace.".to_string())]);
+    }
 
+    #[test]
+    fn interpolations_across_lines() {
+        assert_eq!(errors("\"a\n{\n1 +}\";"), vec![(3, 4, "End of token stream while parsing primary expression.".to_string())]);
+    }
 
+    #[test]
+    fn parsing_resumes_after_an_error() {
+        assert_eq!(errors("a := ;\nb := 1;\nc = );"), vec![
+            (1, 6, "Invalid Syntax while parsing primary expression.".to_string()),
+            (3, 5, "Invalid Syntax while parsing primary expression.".to_string()),
+        ]);
+    }
+}
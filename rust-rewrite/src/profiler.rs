@@ -0,0 +1,181 @@
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::ast::{StatSeq, Stmt};
+use crate::executor::{catch, raise, Frame, Hooks, Obj, Scope};
+
+
+//`oko run --profile`, measures where a program spends its time.
+//time is charged to whatever runs between two events (a statement starting, a call, a return),
+//so it includes the overhead of measuring. afterwards it prints:
+// - every function with its calls, total time (including what it called) and self time
+// - the lines that took the longest, not counting the calls made on them
+//and writes the time per call stack in the folded format of flamegraph tools, f.e.:
+//  <program>;fib;fib 1234
+//with the time in microseconds.
+
+const HOT_LINES: usize = 10;
+
+#[derive(Default)]
+struct FunStats {
+    calls: u64,
+    total: Duration,
+    own: Duration, //without the functions it called
+}
+
+#[derive(Default)]
+struct LineStats {
+    hits: u64,
+    time: Duration,
+}
+
+//a call in progress
+struct Call {
+    name: String,
+    start: Instant,
+    path_len: usize, //where it ends in the folded path
+}
+
+struct Profiler {
+    stack: Vec<Call>, //the program itself is at the bottom
+    path: String, //the names on the stack joined by `;`, kept along with it so charging doesn't allocate
+    line: u32, //the line being run at the top of the stack
+    last: Instant, //when time was last charged
+    funs: HashMap<String, FunStats>,
+    lines: HashMap<u32, LineStats>,
+    folded: HashMap<String, Duration>,
+}
+
+impl Profiler {
+    //charges the time since the last event to what was running in between
+    fn charge(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+
+        //every function on the stack got its entry when it was entered
+        let top = self.stack.last().unwrap();
+        self.funs.get_mut(&top.name).unwrap().own += elapsed;
+        self.lines.entry(self.line).or_default().time += elapsed;
+        match self.folded.get_mut(&self.path) {
+            Some(x) => *x += elapsed,
+            None => { self.folded.insert(self.path.clone(), elapsed); },
+        }
+    }
+
+    fn push(&mut self, name: &str) {
+        self.path.push(';');
+        self.path.push_str(name);
+        self.stack.push(Call { name: name.to_string(), start: self.last, path_len: self.path.len() });
+    }
+
+    //ends the call at the top of the stack
+    fn pop(&mut self) {
+        let call = self.stack.pop().unwrap();
+        self.path.truncate(self.stack.last().unwrap().path_len);
+        //a recursive call is already part of the total of the outermost one
+        if !self.stack.iter().any(|x| x.name == call.name) {
+            self.funs.entry(call.name).or_default().total += self.last - call.start;
+        }
+    }
+}
+
+impl Hooks for Profiler {
+    fn stmt(&mut self, stmt: &Stmt, scope: &mut Scope) {
+        self.charge();
+        //calls that a caught error unwound out of never returned
+        self.stack.truncate(scope.frame.as_ref().map_or(0, |x| x.depth) + 1);
+        self.path.truncate(self.stack.last().unwrap().path_len);
+        self.line = stmt.span.line;
+        self.lines.entry(self.line).or_default().hits += 1;
+    }
+
+    fn entered(&mut self, frame: &Frame) {
+        self.charge();
+        self.funs.entry(frame.name.clone()).or_default().calls += 1;
+        self.push(&frame.name);
+    }
+
+    fn returned(&mut self, frame: &Frame, _: &Obj, _: &mut Scope) {
+        self.charge();
+        self.pop();
+        self.line = frame.line;
+    }
+}
+
+
+fn millis(time: Duration) -> String {
+    format!("{:.3}ms", time.as_secs_f64() * 1000.0)
+}
+
+fn summary(profiler: &Profiler, source: &str, total: Duration) -> String {
+    let mut out = format!("Profile, {} in total:\n\n", millis(total));
+
+    let mut funs: Vec<(&String, &FunStats)> = profiler.funs.iter().collect();
+    funs.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+    let width = funs.iter().map(|x| x.0.len()).max().unwrap_or(0).max("Function".len());
+    out += &format!("{:width$}  {:>10}  {:>12}  {:>12}\n", "Function", "Calls", "Total", "Self", width = width);
+    for (name, stats) in funs {
+        out += &format!(
+            "{:width$}  {:>10}  {:>12}  {:>12}\n",
+            name, stats.calls, millis(stats.total), millis(stats.own), width = width,
+        );
+    }
+
+    let mut lines: Vec<(&u32, &LineStats)> = profiler.lines.iter().filter(|x| *x.0 > 0).collect();
+    lines.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+    out += &format!("\nHot lines:\n{:>6}  {:>10}  {:>12}\n", "Line", "Hits", "Time");
+    for (line, stats) in lines.into_iter().take(HOT_LINES) {
+        let text = source.lines().nth(*line as usize - 1).unwrap_or("").trim();
+        out += &format!("{:>6}  {:>10}  {:>12}  {}\n", line, stats.hits, millis(stats.time), text);
+    }
+    out
+}
+
+
+//prints the summary to stderr and writes the folded stacks to `folded_path`
+fn report(profiler: &mut Profiler, source: &str, start: Instant, folded_path: &str) -> Result<(), String> {
+    profiler.charge();
+    //the calls an uncaught error unwound out of
+    while profiler.stack.len() > 1 {
+        profiler.pop();
+    }
+    let total = start.elapsed();
+    profiler.funs.get_mut("<program>").unwrap().total = total;
+    eprint!("{}", summary(profiler, source, total));
+
+    let mut folded: Vec<String> = profiler.folded.iter()
+        .filter(|x| x.1.as_micros() > 0)
+        .map(|(path, time)| format!("{} {}\n", path, time.as_micros()))
+        .collect();
+    folded.sort();
+    if fs::write(folded_path, folded.concat()).is_err() {
+        return Err(format!("Unable to write the folded stacks to '{}'.", folded_path));
+    }
+    eprintln!("\nFolded stacks were written to {}.", folded_path);
+    Ok(())
+}
+
+//runs the program, then reports where it spent its time.
+//a program that ends in a runtime error gets its profile too, the error is raised again afterwards
+pub fn run(source: &str, root: &StatSeq, scope: &mut Scope, folded_path: &str) -> Result<(), String> {
+    let start = Instant::now();
+    let program = "<program>".to_string();
+    let profiler = Rc::new(RefCell::new(Profiler {
+        stack: vec![Call { name: program.clone(), start, path_len: program.len() }], path: program,
+        line: 0, last: start, funs: HashMap::new(), lines: HashMap::new(), folded: HashMap::new(),
+    }));
+    profiler.borrow_mut().funs.entry("<program>".to_string()).or_default().calls = 1;
+
+    scope.hooks = Some(profiler.clone());
+    let result = catch(|| root.eval(scope));
+    scope.hooks = None;
+
+    report(&mut profiler.borrow_mut(), source, start, folded_path)?;
+    if let Err(x) = result { raise(x) }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+
+use std::io::{self, BufRead, Write};
+
+use crate::ast::StmtKind;
+use crate::executor::{catch, format_error, Obj, Scope};
+use crate::lexer::{self, TokenClass};
+use crate::parser;
+
+
+//an entry spanning multiple lines, like a function, is finished once every `{` is closed
+fn is_complete(source: &str) -> bool {
+    let stream = lexer::lex(source);
+    let mut depth = 0;
+    for token in stream.tokens() {
+        match token.data {
+            TokenClass::CurlyOpen  => depth += 1,
+            TokenClass::CurlyClose => depth -= 1,
+            _ => {},
+        }
+    }
+    depth <= 0
+}
+
+fn eval_entry(source: &str, scope: &mut Scope) {
+    let mut stream = lexer::lex(source);
+    let root = parser::parse_program(&mut stream);
+    if lexer::report_all(source, &stream) > 0 { return }
+
+    for stmt in &root.stmts {
+        //echo the value of bare expressions, so `1 + 2` shows `3`. Nil is skipped, so `io::println(x)` only prints once
+        let StmtKind::Expr(expr) = &stmt.kind else {
+            stmt.eval(scope);
+            continue;
+        };
+        match expr.eval(scope) {
+            Obj::Nil => {},
+            x => println!("{}", x),
+        }
+    }
+}
+
+
+//every entry runs in the same scope, so variables and functions carry over between them,
+//including the ones an entry defined before it failed
+pub fn run(scope: &mut Scope) {
+    println!("oko {} - press Ctrl+D to exit.", env!("CARGO_PKG_VERSION"));
+
+    let stdin = io::stdin();
+    let mut buffer = String::new();
+    loop {
+        print!("{}", if buffer.is_empty() { ">> " } else { ".. " });
+        _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {},
+        }
+
+        if buffer.is_empty() && line.trim().is_empty() { continue }
+        buffer.push_str(&line);
+        if !is_complete(&buffer) { continue }
+
+        //the semicolon after a one-liner is optional here
+        let mut source = std::mem::take(&mut buffer);
+        if !source.trim_end().ends_with([';', '}']) {
+            source.push(';');
+        }
+        //an error only ends the entry, not the session
+        if let Err(x) = catch(|| eval_entry(&source, scope)) {
+            eprintln!("{}", format_error(&x));
+        }
+    }
+    println!();
+}
@@ -0,0 +1,40 @@
+
+use crate::ast::{self, Expr, ExprKind, StatSeq, Stmt, StmtKind, VisitorMut};
+
+
+//marks every `return f(...)` that sits inside of `f` itself.
+//the executor runs those by restarting the current call instead of nesting a new one,
+//so tail-recursive functions run in constant stack space, f.e.:
+//  fun count(n, acc) { if (n == 0) { return acc; } return count(n - 1, acc + 1); }
+struct TailCalls {
+    function: Option<String>, //the innermost function being visited
+}
+
+impl VisitorMut for TailCalls {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        match &mut stmt.kind {
+            StmtKind::Fun(decl) => {
+                let outer = self.function.replace(decl.name.clone());
+                ast::walk_stmt_mut(self, stmt);
+                self.function = outer;
+                return;
+            },
+            //the call has to return into the `try`, for its errors to be caught and `finally` to run after it
+            StmtKind::Try { .. } => {
+                let outer = self.function.take();
+                ast::walk_stmt_mut(self, stmt);
+                self.function = outer;
+                return;
+            },
+            StmtKind::Return { expr: Some(Expr { kind: ExprKind::Call(call), .. }), tail_call } => {
+                *tail_call = self.function.as_ref() == Some(&call.name);
+            },
+            _ => {},
+        }
+        ast::walk_stmt_mut(self, stmt);
+    }
+}
+
+pub fn mark(root: &mut StatSeq) {
+    TailCalls { function: None }.visit_block_mut(root);
+}
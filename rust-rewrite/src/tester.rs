@@ -0,0 +1,125 @@
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::StmtKind;
+use crate::executor::{call_function, catch, format_error, Scope};
+use crate::lexer;
+use crate::parser;
+
+
+//`oko test`, runs the `test_*` functions of `*_test.oko` files.
+//the top level of a file runs first, so its functions and variables are there for the tests.
+//every test is called on its own, a call can't change the variables of its caller,
+//so one test can't affect the next. a test fails once a runtime error ends it,
+//f.e. one of the `assert` module.
+
+//finds the test files in a directory and the ones below it, hidden ones are skipped
+fn discover(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Err(format!("Unable to read directory '{}'.", dir.display()));
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|x| x.ok()).map(|x| x.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let name = path.file_name().map_or(String::new(), |x| x.to_string_lossy().into_owned());
+        if name.starts_with('.') { continue }
+        if path.is_dir() {
+            discover(&path, files)?;
+        } else if name.ends_with("_test.oko") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Results {
+    passed: usize,
+    failed: usize,
+}
+
+impl Results {
+    fn fail(&mut self, name: &str, msg: &str) {
+        self.failed += 1;
+        println!("    FAILED  {}", name);
+        for line in msg.lines() {
+            println!("            {}", line);
+        }
+    }
+}
+
+fn run_file(path: &Path, scope: &Scope, results: &mut Results) {
+    println!("{}", path.display());
+    let Ok(source) = fs::read_to_string(path) else {
+        return results.fail("<file>", &format!("Unable to read source file '{}'.", path.display()));
+    };
+    let mut stream = lexer::lex(&source);
+    let root = parser::parse_program(&mut stream);
+    let count = lexer::report_all(&source, &stream);
+    if count > 0 {
+        return results.fail("<file>", &format!("The file has {} syntax error(s).", count));
+    }
+
+    let tests: Vec<_> = root.stmts.iter()
+        .filter_map(|x| match &x.kind {
+            StmtKind::Fun(decl) if decl.name.starts_with("test_") => Some(decl),
+            _ => None,
+        })
+        .collect();
+    if tests.is_empty() {
+        println!("    no tests");
+        return;
+    }
+
+    let mut scope = scope.clone();
+    if let Err(x) = catch(|| root.eval(&mut scope)) {
+        //without its top level, none of the tests can run
+        for test in tests {
+            results.fail(&test.name, &format!("The top level of the file failed.\n{}", format_error(&x)));
+        }
+        return;
+    }
+
+    for test in tests {
+        if test.args.iter().any(|x| x.default.is_none()) {
+            results.fail(&test.name, "Test functions can't have parameters without a default.");
+            continue;
+        }
+        match catch(|| call_function(&mut scope.clone(), &test.name, vec![], 0)) {
+            Ok(_) => {
+                results.passed += 1;
+                println!("    ok      {}", test.name);
+            },
+            Err(x) => results.fail(&test.name, &format_error(&x)),
+        }
+    }
+}
+
+
+//`paths` are test files, or directories to look for them in.
+//every file runs in a copy of `scope`, returns whether all tests passed
+pub fn run(paths: &[String], scope: &Scope) -> Result<bool, String> {
+    let mut files = vec![];
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            discover(path, &mut files)?;
+        } else if path.exists() {
+            files.push(path.to_path_buf());
+        } else {
+            return Err(format!("There is no file or directory '{}'.", path.display()));
+        }
+    }
+    if files.is_empty() {
+        return Err("No test files were found, their names have to end in `_test.oko`.".to_string());
+    }
+
+    let mut results = Results::default();
+    for file in &files {
+        run_file(file, scope, &mut results);
+    }
+    println!("\n{} passed, {} failed.", results.passed, results.failed);
+    Ok(results.failed == 0)
+}